@@ -23,9 +23,9 @@ use pew::Benchmark;
 fn get_vec(n: u64) -> Vec<u64> {
     let mut vec = Vec::new();
     for i in 0..n {
-        vec.push(i as u64);
+        vec.push(i);
     }
-    return vec;
+    vec
 }
 
 fn bm_vector_range(state: &mut pew::State<u64>) {
@@ -57,4 +57,6 @@ fn main() {
         .with_generator(get_vec)
         .with_bench(pew_bench!(bm_vector_gen))
         .run();
+
+    pew::finish();
 }
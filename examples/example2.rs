@@ -28,7 +28,7 @@ fn get_vec(n: u64) -> Vec<u64> {
     for _ in 0..n {
         vec.push(rng.gen::<u64>());
     }
-    return vec;
+    vec
 }
 
 fn bm_vector_iterate(state: &mut pew::State<Vec<u64>>) {
@@ -54,4 +54,6 @@ fn main() {
         .with_bench(pew_bench!(bm_vector_iterate))
         .with_bench(pew_bench!(bm_vector_delete))
         .run();
+
+    pew::finish();
 }
@@ -0,0 +1,192 @@
+use std::iter::FromIterator;
+use std::ops::{Range, RangeInclusive};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Geometric { lb: u64, ub: u64, mul: u64 },
+    Linear { lb: u64, ub: u64, step: u64 },
+    List(Vec<u64>),
+}
+
+/// The sequence of values a single argument of a benchmark takes
+///
+/// Every spec is validated when it is created, so a spec that would never finish (e.g. a
+/// geometric sequence with `mul = 1`) or that has no values (e.g. a lower bound above the upper
+/// bound) panics right away rather than when the benchmark runs. A sequence stops before it would
+/// overflow a `u64`.
+///
+/// A `(lb, ub, mul)` tuple converts to a geometric spec and a range of `u64` to a dense one.
+///
+/// # Examples
+///
+/// ```
+/// use pew::ArgSpec;
+///
+/// assert_eq!(ArgSpec::geometric(1, 100, 10).values(), [1, 10, 100]);
+/// assert_eq!(ArgSpec::geometric(1, u64::MAX, 1 << 32).values(), [1, 1 << 32]);
+/// assert_eq!(ArgSpec::linear(0, 10, 4).values(), [0, 4, 8]);
+/// assert_eq!(ArgSpec::list(&[3, 1, 2]).values(), [3, 1, 2]);
+/// assert_eq!(ArgSpec::from(5..8).values(), [5, 6, 7]);
+/// assert_eq!(
+///     (1..4).map(|i| i * i).collect::<ArgSpec>().values(),
+///     [1, 4, 9]
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArgSpec(Kind);
+
+impl ArgSpec {
+    /// `lb, lb * mul, lb * mul^2, ...` up to and including `ub`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is 0 or greater than `ub`, or if `mul` is less than 2.
+    ///
+    /// ```should_panic
+    /// use pew::ArgSpec;
+    ///
+    /// ArgSpec::geometric(1, 1 << 20, 1);
+    /// ```
+    pub fn geometric(lb: u64, ub: u64, mul: u64) -> Self {
+        if lb == 0 {
+            panic!("The lower bound of a geometric sequence must be positive");
+        }
+        check_bounds(lb, ub);
+        if mul < 2 {
+            panic!(
+                "The multiplier of a geometric sequence must be at least 2, got {}",
+                mul
+            );
+        }
+        ArgSpec(Kind::Geometric { lb, ub, mul })
+    }
+
+    /// `lb, lb + step, lb + 2 * step, ...` up to and including `ub`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is greater than `ub`, or if `step` is 0.
+    pub fn linear(lb: u64, ub: u64, step: u64) -> Self {
+        if step == 0 {
+            panic!("The step of a linear sequence must be positive");
+        }
+        check_bounds(lb, ub);
+        ArgSpec(Kind::Linear { lb, ub, step })
+    }
+
+    /// Every value from `lb` up to and including `ub`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is greater than `ub`.
+    pub fn dense(lb: u64, ub: u64) -> Self {
+        ArgSpec::linear(lb, ub, 1)
+    }
+
+    /// Exactly `values`, in the given order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` is empty.
+    ///
+    /// ```should_panic
+    /// use pew::ArgSpec;
+    ///
+    /// ArgSpec::list(&[]);
+    /// ```
+    pub fn list(values: &[u64]) -> Self {
+        ArgSpec::from_values(values.to_vec())
+    }
+
+    fn from_values(values: Vec<u64>) -> Self {
+        if values.is_empty() {
+            panic!("An argument spec must have at least one value");
+        }
+        ArgSpec(Kind::List(values))
+    }
+
+    /// Returns the values of this spec, in the order the benchmark is run with them
+    pub fn values(&self) -> Vec<u64> {
+        let (lb, ub) = match self.0 {
+            Kind::Geometric { lb, ub, .. } | Kind::Linear { lb, ub, .. } => (lb, ub),
+            Kind::List(ref values) => return values.clone(),
+        };
+
+        let mut values = Vec::new();
+        let mut i = Some(lb);
+        while let Some(value) = i.filter(|value| *value <= ub) {
+            values.push(value);
+            i = self.next(value);
+        }
+        values
+    }
+
+    /// Returns the value after `i` in a geometric or linear sequence, or `None` on overflow
+    fn next(&self, i: u64) -> Option<u64> {
+        match self.0 {
+            Kind::Geometric { mul, .. } => i.checked_mul(mul),
+            Kind::Linear { step, .. } => i.checked_add(step),
+            Kind::List(_) => None,
+        }
+    }
+
+    /// Returns the bounds and multiplier of a geometric spec
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is not a geometric spec.
+    pub(crate) fn geometric_parts(&self) -> (u64, u64, u64) {
+        match self.0 {
+            Kind::Geometric { lb, ub, mul } => (lb, ub, mul),
+            _ => panic!("Only the bounds of a geometric range can be set individually"),
+        }
+    }
+}
+
+impl From<(u64, u64, u64)> for ArgSpec {
+    fn from((lb, ub, mul): (u64, u64, u64)) -> Self {
+        ArgSpec::geometric(lb, ub, mul)
+    }
+}
+
+/// # Panics
+///
+/// Panics if the range is empty.
+impl From<Range<u64>> for ArgSpec {
+    fn from(range: Range<u64>) -> Self {
+        if range.start >= range.end {
+            panic!(
+                "An argument range must have at least one value, got {}..{}",
+                range.start, range.end
+            );
+        }
+        ArgSpec::dense(range.start, range.end - 1)
+    }
+}
+
+impl From<RangeInclusive<u64>> for ArgSpec {
+    fn from(range: RangeInclusive<u64>) -> Self {
+        ArgSpec::dense(*range.start(), *range.end())
+    }
+}
+
+/// Collects the values of a custom (finite) iterator into a spec
+///
+/// # Panics
+///
+/// Panics if the iterator is empty.
+impl FromIterator<u64> for ArgSpec {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        ArgSpec::from_values(iter.into_iter().collect())
+    }
+}
+
+/// Panics if a sequence from `lb` to `ub` would have no values
+fn check_bounds(lb: u64, ub: u64) {
+    if lb > ub {
+        panic!(
+            "The lower bound of a sequence cannot exceed its upper bound, got {} and {}",
+            lb, ub
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use clock::ClockSource;
+use libc;
+use reporter;
+use result::{BenchmarkResult, RunType};
+use serde_json::{self, Map, Value};
+use stats::{self, Statistics};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+
+/// A change is only considered significant if the p-value of the test is below this
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+lazy_static! {
+    /// The threshold of `--fail-if-slower` and the name and comparison of every benchmark that
+    /// exceeded it since the last call to `report_regressions`
+    static ref REGRESSIONS: Mutex<(f64, Vec<(String, Comparison)>)> =
+        Mutex::new((0.0, Vec::new()));
+}
+
+static REPORT_REGRESSIONS_AT_EXIT: Once = Once::new();
+
+/// Whether a benchmark got faster or slower than its baseline (see `Comparison`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Faster,
+    Slower,
+    /// The difference is not statistically significant
+    Unchanged,
+}
+
+impl Verdict {
+    /// Returns the name of this verdict as it is reported, e.g. `slower`
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Faster => "faster",
+            Verdict::Slower => "slower",
+            Verdict::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// How a benchmark compares to the same benchmark in a saved baseline (see `Config::with_baseline`)
+///
+/// The samples of the clock the benchmark was run with (see `ClockSource`) are compared with
+/// Welch's t-test. The change is only reported as faster or slower if its p-value is below 0.05.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    /// The name of the baseline
+    pub baseline: String,
+    /// The mean time of an iteration in the baseline, in ns
+    pub baseline_mean: f64,
+    /// The relative change of the mean time, e.g. `0.05` if the benchmark got 5% slower
+    pub change: f64,
+    /// The 95% confidence interval of the relative change
+    pub interval: (f64, f64),
+    /// The probability of measuring a difference at least this large if the benchmark did not
+    /// change
+    pub p_value: f64,
+    pub verdict: Verdict,
+}
+
+impl Comparison {
+    /// Compares the samples of a benchmark to the samples of the same benchmark in the baseline
+    /// called `baseline`
+    ///
+    /// Returns `None` if either has fewer than 2 samples.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{Comparison, Verdict};
+    ///
+    /// let comparison = Comparison::from_samples("main", &[10.0, 11.0, 9.0], &[20.0, 21.0, 19.0])
+    ///     .unwrap();
+    /// assert_eq!(comparison.baseline_mean, 10.0);
+    /// assert_eq!(comparison.change, 1.0);
+    /// assert_eq!(comparison.verdict, Verdict::Slower);
+    /// ```
+    pub fn from_samples(baseline: &str, baseline_samples: &[f64], samples: &[f64]) -> Option<Self> {
+        if baseline_samples.len() < 2 || samples.len() < 2 {
+            return None;
+        }
+
+        let baseline_mean = Statistics::from_samples(baseline_samples).mean;
+        let change = Statistics::from_samples(samples).mean / baseline_mean - 1.0;
+        let test = stats::welch_t_test(samples, baseline_samples, SIGNIFICANCE_LEVEL);
+        let (lo, hi) = test.interval;
+        let verdict = if test.p_value >= SIGNIFICANCE_LEVEL {
+            Verdict::Unchanged
+        } else if change < 0.0 {
+            Verdict::Faster
+        } else {
+            Verdict::Slower
+        };
+        Some(Comparison {
+            baseline: baseline.to_string(),
+            baseline_mean,
+            change,
+            interval: (lo / baseline_mean, hi / baseline_mean),
+            p_value: test.p_value,
+            verdict,
+        })
+    }
+
+    /// Returns whether the benchmark is significantly slower than the baseline by more than
+    /// `percent` (see `Config::with_fail_if_slower`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::Comparison;
+    ///
+    /// let comparison = Comparison::from_samples("main", &[10.0, 11.0, 9.0], &[20.0, 21.0, 19.0])
+    ///     .unwrap();
+    /// assert!(comparison.is_regression(5.0));
+    /// assert!(!comparison.is_regression(200.0));
+    /// ```
+    pub fn is_regression(&self, percent: f64) -> bool {
+        self.verdict == Verdict::Slower && self.change * 100.0 > percent
+    }
+}
+
+/// The results saved with `--save-baseline`, indexed by the name of the benchmark
+pub(crate) struct Baseline {
+    name: String,
+    benchmarks: Map<String, Value>,
+}
+
+impl Baseline {
+    /// Loads the baseline called `name` from `dir`
+    pub(crate) fn load(dir: &Path, name: &str) -> io::Result<Baseline> {
+        read(&path(dir, name)).map(|benchmarks| Baseline {
+            name: name.to_string(),
+            benchmarks,
+        })
+    }
+
+    /// Compares a result to the same benchmark in the baseline
+    ///
+    /// Returns `None` if the baseline does not have the benchmark, if the result is not a
+    /// repetition, or if either has fewer than 2 samples.
+    pub(crate) fn compare(&self, result: &BenchmarkResult) -> Option<Comparison> {
+        if let RunType::Aggregate(_) | RunType::BigO(_) | RunType::Rms(_) = result.run_type {
+            return None;
+        }
+        let samples_key = match result.clock {
+            ClockSource::Monotonic => "real_samples",
+            _ => "cpu_samples",
+        };
+        let baseline: Vec<f64> = self.benchmarks.get(&result.name())?[samples_key]
+            .as_array()?
+            .iter()
+            .filter_map(|sample| sample.as_f64())
+            .collect();
+        let samples = match result.clock {
+            ClockSource::Monotonic => &result.real_samples,
+            _ => &result.cpu_samples,
+        };
+        Comparison::from_samples(&self.name, &baseline, samples)
+    }
+}
+
+/// Records every benchmark in `results` that is significantly slower than the baseline by more
+/// than `percent` (see `Comparison::is_regression`), to be reported by `report_regressions`
+pub(crate) fn record_regressions(results: &[BenchmarkResult], percent: f64) {
+    let mut regressions = REGRESSIONS.lock().unwrap();
+    regressions.0 = percent;
+    regressions.1.extend(results.iter().filter_map(|result| {
+        result
+            .comparison
+            .as_ref()
+            .filter(|comparison| comparison.is_regression(percent))
+            .map(|comparison| (result.name(), comparison.clone()))
+    }));
+}
+
+/// Reports the regressions recorded by `Benchmark::run` when the process exits, unless
+/// `pew::finish` did so before
+pub(crate) fn report_regressions_at_exit() {
+    REPORT_REGRESSIONS_AT_EXIT.call_once(|| unsafe {
+        libc::atexit(exit_on_regressions);
+    });
+}
+
+/// Makes the process exit with status 1 if there are regressions, like `pew::finish`
+extern "C" fn exit_on_regressions() {
+    if report_regressions() {
+        // `exit` must not be called again while it runs the `atexit` handlers
+        unsafe { libc::_exit(1) }
+    }
+}
+
+/// Prints every regression recorded so far to stderr with the confidence interval of its change,
+/// and forgets them
+///
+/// Returns whether there were any.
+pub(crate) fn report_regressions() -> bool {
+    // This also runs when the process exits, where it must not panic
+    let (percent, regressions): (f64, Vec<(String, Comparison)>) = match REGRESSIONS.lock() {
+        Ok(mut regressions) => (regressions.0, regressions.1.drain(..).collect()),
+        Err(_) => return false,
+    };
+    if regressions.is_empty() {
+        return false;
+    }
+
+    eprintln!(
+        "Error: {} benchmark(s) are more than {}% slower than the baseline:",
+        regressions.len(),
+        percent
+    );
+    for (name, comparison) in regressions {
+        eprintln!(
+            "  {}: {:+.2}% (95% CI {:+.2}% to {:+.2}%, p={:.3}) compared to {}",
+            name,
+            comparison.change * 100.0,
+            comparison.interval.0 * 100.0,
+            comparison.interval.1 * 100.0,
+            comparison.p_value,
+            comparison.baseline
+        );
+    }
+    true
+}
+
+/// Saves every repetition in `results` to the baseline called `name` in `dir`
+///
+/// The samples of every repetition of a benchmark are pooled. Benchmarks that are already in the
+/// baseline are replaced, and the others are kept, so a baseline can be built up by several
+/// suites or programs.
+pub(crate) fn save(dir: &Path, name: &str, results: &[BenchmarkResult]) {
+    let path = path(dir, name);
+    let mut benchmarks = read(&path).unwrap_or_default();
+
+    let mut pooled: BTreeMap<String, (&BenchmarkResult, Vec<f64>, Vec<f64>)> = BTreeMap::new();
+    for result in results {
+        if let RunType::Repetition(_) = result.run_type {
+            let entry = pooled
+                .entry(result.name())
+                .or_insert((result, Vec::new(), Vec::new()));
+            entry.1.extend(&result.real_samples);
+            entry.2.extend(&result.cpu_samples);
+        }
+    }
+    for (bm_name, (result, real_samples, cpu_samples)) in pooled {
+        let benchmark = json!({
+            "clock": result.clock.name(),
+            "iterations": result.iterations,
+            "real_time": reporter::stats_json(&Statistics::from_samples(&real_samples)),
+            "cpu_time": reporter::stats_json(&Statistics::from_samples(&cpu_samples)),
+            "real_samples": real_samples,
+            "cpu_samples": cpu_samples,
+        });
+        benchmarks.insert(bm_name, benchmark);
+    }
+
+    let baseline = json!({ "benchmarks": benchmarks });
+    let written = fs::create_dir_all(dir).and_then(|_| {
+        let contents = serde_json::to_string(&baseline).expect("JSON serialization failed");
+        fs::write(&path, contents)
+    });
+    if let Err(e) = written {
+        eprintln!(
+            "Warning: cannot save baseline {} to {}: {}",
+            name,
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Returns the file the baseline called `name` is saved in
+///
+/// A name ending in `.json` is the path of the file rather than a name.
+fn path(dir: &Path, name: &str) -> PathBuf {
+    if Path::new(name).extension() == Some("json".as_ref()) {
+        return PathBuf::from(name);
+    }
+    dir.join(format!("{}.json", name))
+}
+
+/// Reads the benchmarks of a saved baseline or of the output of the `JsonReporter`
+///
+/// The samples of every repetition in the output of the `JsonReporter` are pooled like those of a
+/// saved baseline.
+fn read(path: &Path) -> io::Result<Map<String, Value>> {
+    let contents = fs::read_to_string(path)?;
+    let mut benchmarks = Map::new();
+    for document in serde_json::Deserializer::from_str(&contents).into_iter::<Value>() {
+        let document = document.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match document["benchmarks"] {
+            Value::Object(ref saved) => benchmarks.extend(saved.clone()),
+            Value::Array(ref reported) => {
+                for benchmark in reported.iter().filter(|b| b["run_type"] == "repetition") {
+                    let name = benchmark["name"].as_str().unwrap_or_default().to_string();
+                    let entry = benchmarks
+                        .entry(name)
+                        .or_insert_with(|| json!({"real_samples": [], "cpu_samples": []}));
+                    for key in &["real_samples", "cpu_samples"] {
+                        if let (Some(pooled), Some(samples)) =
+                            (entry[*key].as_array_mut(), benchmark[*key].as_array())
+                        {
+                            pooled.extend(samples.iter().cloned());
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing \"benchmarks\"",
+                ))
+            }
+        }
+    }
+    Ok(benchmarks)
+}
@@ -1,40 +1,551 @@
-use config::Config;
-use state::State;
-use std::sync::{Once, ONCE_INIT};
+use arg_spec::ArgSpec;
+use baseline::{self, Baseline};
+use clock::{Calibration, Clock, ClockSource, Elapsed};
+use complexity::{Complexity, Fit};
+use config::{self, Config};
+use counter::Counter;
+use fixture::Fixture;
+use reporter::{Context, Reporter};
+use result::{Aggregate, BenchmarkResult, RunType};
+use state::{Input, State};
+use std::cmp;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
 
-static HEADER: Once = ONCE_INIT;
+type LocalFn<T> = Box<dyn Fn(&mut State<T>)>;
+type SyncFn<T> = Box<dyn Fn(&mut State<T>) + Sync>;
+type RunFn<'a, T> = &'a dyn Fn(&mut State<T>);
+type SyncRunFn<'a, T> = &'a (dyn Fn(&mut State<T>) + Sync);
+type ThreadRuns<T> = (Vec<(Input<T>, thread::Result<Run>)>, u64);
+type ThreadsFn<T> = for<'a> fn(SyncRunFn<'a, T>, Vec<Input<T>>, u64) -> ThreadRuns<T>;
+type Generator<T> = Box<dyn Fn(&[u64]) -> T>;
+type ProcessedFn = Box<dyn Fn(u64) -> u64>;
+type FitPoints<'a> = (&'a [u64], Vec<(u64, f64, f64)>);
 
-fn should_run_bm(bm_name: &String) -> bool {
-    let filter = &Config::get().filter;
+/// Runs that iterate (see `State::iter`) are made to take at least this long (in ns), or 1000
+/// times the resolution of the clock if that is longer, so that the overhead and resolution of
+/// the clock are negligible compared to the measured time.
+const MIN_RUN_DURATION: u64 = 100_000;
 
-    filter.is_match(bm_name)
+/// A warning is printed for benchmarks whose runs take less than this many times the overhead or
+/// resolution of the clock
+const OVERHEAD_WARNING_FACTOR: u64 = 10;
+
+/// The most iterations a single run will be asked to do
+const MAX_ITERATIONS: u64 = 1_000_000_000;
+
+fn should_run_bm(config: &Config, bm_name: &str) -> bool {
+    config.filter.is_match(bm_name)
+}
+
+/// How each run of a benchmark gets its input
+///
+/// The generator is called once per argument, and by default every run gets a clone of its
+/// result. Bench functions that only read their input can share it instead, and bench functions
+/// whose input is expensive to clone (or that rely on state a clone does not copy) can have it
+/// generated again for every run. Generating or cloning the input is never timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputStrategy {
+    /// Every run gets its own clone of the generated input. This is the default.
+    Clone,
+    /// Every run shares the same input, which is read with `State::input`. It is only cloned if
+    /// a run asks for it mutably (see `State::input_mut`).
+    Shared,
+    /// The generator is called again for every run
+    Regenerate,
+}
+
+/// How the input of every run is derived from the output of the generator
+enum InputKind<T> {
+    /// The output of the generator, cloned with the given function (see `InputStrategy`)
+    Generated(fn(&T) -> T),
+    /// A `Fixture`, with its `reset` and `teardown` hooks
+    Fixture(fn(&mut T), fn(T)),
+}
+
+/// The source of the inputs of the runs of a single argument
+enum Inputs<'a, T> {
+    Cloned(T, fn(&T) -> T),
+    Shared(Arc<T>, fn(&T) -> T),
+    Regenerated(&'a Generator<T>, &'a [u64]),
+    /// The fixture while it is not lent to a run, with its `reset` and `teardown` hooks
+    Fixture(Option<T>, fn(&mut T), fn(T)),
+}
+
+impl<'a, T> Inputs<'a, T> {
+    /// Returns the input of the next run
+    fn next(&mut self) -> Input<T> {
+        match *self {
+            Inputs::Cloned(ref input, clone) => Input::Owned(clone(input)),
+            Inputs::Shared(ref input, clone) => Input::Shared(input.clone(), clone),
+            Inputs::Regenerated(gen, args) => Input::Owned(gen(args)),
+            Inputs::Fixture(ref mut fixture, reset, _) => {
+                let mut fixture = fixture.take().expect("The fixture is already in use");
+                reset(&mut fixture);
+                Input::Fixture(fixture)
+            }
+        }
+    }
+
+    /// Takes back the input of a run once it is over
+    fn restore(&mut self, input: Input<T>) {
+        if let Inputs::Fixture(ref mut fixture, _, _) = *self {
+            if let Input::Fixture(input) = input {
+                *fixture = Some(input);
+            }
+        }
+    }
+}
+
+impl<'a, T> Drop for Inputs<'a, T> {
+    /// Tears down the fixture, including when a bench function panicked
+    fn drop(&mut self) {
+        if let Inputs::Fixture(ref mut fixture, _, teardown) = *self {
+            if let Some(fixture) = fixture.take() {
+                teardown(fixture);
+            }
+        }
+    }
+}
+
+/// A bench function, which can only be run on several threads at once if it is `Sync` (see
+/// `Benchmark::with_threaded_bench`)
+enum BenchFn<T> {
+    Local(LocalFn<T>),
+    Sync(SyncFn<T>),
+}
+
+impl<T> BenchFn<T> {
+    fn get(&self) -> RunFn<'_, T> {
+        match *self {
+            BenchFn::Local(ref f) => f.as_ref(),
+            BenchFn::Sync(ref f) => f.as_ref(),
+        }
+    }
+
+    fn get_sync(&self) -> Option<SyncRunFn<'_, T>> {
+        match *self {
+            BenchFn::Local(_) => None,
+            BenchFn::Sync(ref f) => Some(f.as_ref()),
+        }
+    }
+}
+
+/// How long a benchmark is run before it is measured
+#[derive(Default)]
+struct Warmup {
+    duration: u64,
+    runs: u64,
+}
+
+/// The work done by each iteration unless the bench function sets its own (see
+/// `State::set_bytes_processed`)
+struct Processed {
+    bytes: Option<u64>,
+    items: Option<u64>,
+}
+
+/// The threads a benchmark is run on (see `Benchmark::with_threads`)
+struct Threads<T> {
+    counts: Vec<u64>,
+    /// `run_threads`, for a `T` that can be sent to other threads
+    ///
+    /// This is `None` after `Benchmark::with_generator` and `Benchmark::with_fixture` until a
+    /// bench function is added with `Benchmark::with_threaded_bench`.
+    run: Option<ThreadsFn<T>>,
+}
+
+/// What a single run of a bench function on a single thread measured
+struct Run {
+    elapsed: Elapsed,
+    pauses: u64,
+    uses_iterations: bool,
+    bytes: Option<u64>,
+    items: Option<u64>,
+    counters: Vec<(String, Counter)>,
 }
 
-fn range_generator<T>(i: T) -> T {
-    i
+/// Runs `f` once, returning the input of the run along with what it measured, or why it panicked
+fn run<T>(f: RunFn<T>, mut state: State<T>) -> (Input<T>, thread::Result<Run>) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(&mut state))) {
+        return (state.into_input(), Err(payload));
+    }
+    let uses_iterations = state.uses_iterations();
+    let pauses = state.pauses();
+    let bytes = state.bytes_processed();
+    let items = state.items_processed();
+    let counters = state.take_counters();
+    let (elapsed, input) = state.finish_with_input();
+    let run = Run {
+        elapsed,
+        pauses,
+        uses_iterations,
+        bytes,
+        items,
+        counters,
+    };
+    (input, Ok(run))
+}
+
+/// Runs `f` once on a thread per input, all of which start together
+///
+/// Every thread times itself with a CPU clock of its own, started once every thread is ready, and
+/// stamps the wall clock when it starts and when it is done. Returns the run of every thread along
+/// with the wall clock time of the whole group, from the first of them starting until the last of
+/// them is done.
+fn run_threads<T: Send + Sync>(
+    f: SyncRunFn<T>,
+    inputs: Vec<Input<T>>,
+    iterations: u64,
+) -> ThreadRuns<T> {
+    let count = inputs.len() as u64;
+    let barrier = Barrier::new(inputs.len());
+    thread::scope(|scope| {
+        let handles: Vec<_> = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    barrier.wait();
+                    let start = Clock::monotonic();
+                    let state = State::for_run(input, ClockSource::ThreadCpu, iterations)
+                        .with_thread(index as u64, count);
+                    let run = run(f, state);
+                    (run, start, Clock::monotonic())
+                })
+            })
+            .collect();
+        let mut runs = Vec::with_capacity(handles.len());
+        let mut first_start = u64::MAX;
+        let mut last_end = 0;
+        for handle in handles {
+            let (run, start, end) = handle
+                .join()
+                .expect("Bench functions are run with catch_unwind");
+            runs.push(run);
+            first_start = cmp::min(first_start, start);
+            last_end = cmp::max(last_end, end);
+        }
+        (runs, last_end.saturating_sub(first_start))
+    })
 }
 
-fn compose<T: 'static, U: 'static>(f: Box<Fn(u64) -> T>, g: fn(T) -> U) -> Box<Fn(u64) -> U> {
-    Box::new(move |i: u64| g(f(i)))
+/// The runs of a single benchmark
+struct Measurement {
+    iterations: u64,
+    warmup_runs: u64,
+    warmup_duration: u64,
+    samples: Vec<Elapsed>,
+    /// The total number of bytes processed by every run in `samples`
+    bytes_processed: Option<u64>,
+    /// The total number of items processed by every run in `samples`
+    items_processed: Option<u64>,
+    /// The sum of every counter over every run in `samples`
+    counters: Vec<(String, Counter)>,
+}
+
+/// Runs `f` with inputs from `input` until both `min_runs` and `min_duration` are satisfied
+///
+/// Returns the number of iterations each run did and the time measured for each run. If `f`
+/// iterates, runs that are too short to be measured accurately are discarded and the number of
+/// iterations is increased until they are not. The runs that satisfy `warmup` are discarded as
+/// well, after which every run that is kept does the same number of iterations. If
+/// `--subtract_overhead` is set, the calibrated overhead of the clock is subtracted from every
+/// run.
+///
+/// If `threads` is set, every run is made up of that many threads running `f` at the same time.
+/// Its wall clock time is that of the whole group of threads, which pausing a thread does not
+/// pause, and its CPU time the sum over every thread. The overhead subtracted from each thread is
+/// that of `ClockSource::ThreadCpu`, which the threads are timed with.
+fn measure<T>(
+    config: &Config,
+    calibration: &Calibration,
+    warmup: &Warmup,
+    processed: &Processed,
+    f: &BenchFn<T>,
+    inputs: &mut Inputs<T>,
+    threads: Option<(ThreadsFn<T>, u64)>,
+) -> Measurement {
+    let clock = calibration.source;
+    let min_run_duration = cmp::max(MIN_RUN_DURATION, calibration.resolution * 1000);
+
+    let mut iterations = 1;
+    let mut warmup_runs = 0;
+    let mut warmup_duration = 0;
+    let mut samples = Vec::new();
+    let mut total_duration = 0;
+    let mut bytes_processed = None;
+    let mut items_processed = None;
+    let mut counters: Vec<(String, Counter)> = Vec::new();
+    while samples.len() < config.min_runs as usize || total_duration < config.min_duration {
+        let (results, group_real) = match threads {
+            Some((run_threads, count)) => {
+                let f = f
+                    .get_sync()
+                    .expect("run_with only runs Sync bench functions on threads");
+                let thread_inputs = (0..count).map(|_| inputs.next()).collect();
+                let (results, real) = run_threads(f, thread_inputs, iterations);
+                (results, Some(real))
+            }
+            None => {
+                let state = State::for_run(inputs.next(), clock, iterations);
+                (vec![run(f.get(), state)], None)
+            }
+        };
+        let mut runs = Vec::with_capacity(results.len());
+        let mut panicked = None;
+        for (input, result) in results {
+            inputs.restore(input);
+            match result {
+                Ok(run) => runs.push(run),
+                Err(payload) => panicked = panicked.or(Some(payload)),
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+
+        let run_calibration = match group_real {
+            Some(_) => Calibration::get(ClockSource::ThreadCpu),
+            None => *calibration,
+        };
+        let mut elapsed = Elapsed::default();
+        for run in &runs {
+            let mut run_elapsed = run.elapsed;
+            if config.subtract_overhead {
+                run_elapsed = run_elapsed.saturating_sub(run_calibration.overhead(run.pauses));
+            }
+            elapsed.real = cmp::max(elapsed.real, run_elapsed.real);
+            elapsed.cpu += run_elapsed.cpu;
+        }
+        if let Some(mut real) = group_real {
+            if config.subtract_overhead {
+                real = real.saturating_sub(Calibration::get(ClockSource::Monotonic).read.real);
+            }
+            elapsed.real = real;
+        }
+        let duration = elapsed.get(clock);
+        let uses_iterations = runs.iter().any(|run| run.uses_iterations);
+        let bytes = runs
+            .iter()
+            .map(|run| run.bytes.or(processed.bytes))
+            .fold(None, add_processed);
+        let items = runs
+            .iter()
+            .map(|run| run.items.or(processed.items))
+            .fold(None, add_processed);
+        let run_counters: Vec<(String, Counter)> =
+            runs.into_iter().flat_map(|run| run.counters).collect();
+
+        if uses_iterations
+            && samples.is_empty()
+            && duration < min_run_duration
+            && iterations < MAX_ITERATIONS
+        {
+            // Aim a bit past the minimum so the next run is very likely long enough, but never
+            // grow by more than 10x at once in case this run was unusually fast.
+            let multiplier = if duration == 0 {
+                10
+            } else {
+                let target = min_run_duration as f64 * 1.4 / duration as f64;
+                (target.ceil() as u64).clamp(2, 10)
+            };
+            iterations = cmp::min(iterations * multiplier, MAX_ITERATIONS);
+            continue;
+        }
+
+        if samples.is_empty() && (warmup_runs < warmup.runs || warmup_duration < warmup.duration) {
+            warmup_runs += 1;
+            warmup_duration += duration;
+            continue;
+        }
+
+        total_duration += duration;
+        samples.push(elapsed);
+        // Saturate rather than overflow on absurd amounts of work, which only skews the rate
+        let per_run = |processed: Option<u64>| processed.map(|p| p.saturating_mul(iterations));
+        bytes_processed = add_processed(bytes_processed, per_run(bytes));
+        items_processed = add_processed(items_processed, per_run(items));
+        for (name, counter) in run_counters {
+            match counters.iter_mut().find(|(n, _)| *n == name) {
+                Some(entry) => entry.1 = Counter::new(entry.1.value + counter.value, counter.flags),
+                None => counters.push((name, counter)),
+            }
+        }
+    }
+
+    Measurement {
+        iterations,
+        warmup_runs,
+        warmup_duration,
+        samples,
+        bytes_processed,
+        items_processed,
+        counters,
+    }
+}
+
+/// Adds the work processed by a thread (or run) to the work processed by the others
+fn add_processed(total: Option<u64>, processed: Option<u64>) -> Option<u64> {
+    match (total, processed) {
+        (Some(total), Some(processed)) => Some(total.saturating_add(processed)),
+        (total, processed) => total.or(processed),
+    }
+}
+
+/// Warns if the runs of a benchmark are too short to be measured accurately by the clock
+fn check_overhead(bm_name: &str, calibration: &Calibration, samples: &[Elapsed]) {
+    let clock = calibration.source;
+    let mean = samples.iter().map(|s| s.get(clock)).sum::<u64>() / samples.len() as u64;
+    let floor = cmp::max(calibration.read.get(clock), calibration.resolution);
+    if mean < floor * OVERHEAD_WARNING_FACTOR {
+        eprintln!(
+            "Warning: {} takes {} ns per run, which is within {}x of the {} ns overhead or \
+             resolution of the clock. Consider using State::iter.",
+            bm_name, mean, OVERHEAD_WARNING_FACTOR, floor
+        );
+    }
+}
+
+/// Fits `complexity` to the mean times of every argument `function` was run with on `threads`
+/// threads
+///
+/// The times are fitted to the first argument. If there are several arguments, a separate fit is
+/// made for every combination of the others, in the order they were run. Returns the `BigO` and
+/// `Rms` results of every fit, leaving out the combinations that `function` was run with fewer than
+/// 2 values of the first argument for.
+fn fit_complexity(
+    group: &str,
+    function: &str,
+    threads: Option<u64>,
+    clock: ClockSource,
+    complexity: Complexity,
+    results: &[BenchmarkResult],
+) -> Vec<BenchmarkResult> {
+    // The other arguments of every fit, with the first argument and times of each of its points
+    let mut points: Vec<FitPoints> = Vec::new();
+    for result in results
+        .iter()
+        .filter(|r| r.function == function && r.threads == threads)
+    {
+        // Repeated benchmarks are represented by the mean over their repetitions
+        let (real_time, cpu_time) = match result.run_type {
+            RunType::Repetition(_) if result.repetitions == 1 => {
+                (result.real_time.mean, result.cpu_time.mean)
+            }
+            RunType::Aggregate(Aggregate::Mean) => result.value().expect("aggregates have a value"),
+            _ => continue,
+        };
+        let others = &result.args[1..];
+        let point = (result.args[0], real_time, cpu_time);
+        match points.iter_mut().find(|(o, _)| *o == others) {
+            Some((_, fit_points)) => fit_points.push(point),
+            None => points.push((others, vec![point])),
+        }
+    }
+
+    let mut fits = Vec::new();
+    for (others, fit_points) in points {
+        if fit_points.len() < 2 {
+            let mut args = vec![fit_points[0].0];
+            args.extend_from_slice(others);
+            eprintln!(
+                "Warning: cannot fit the complexity of {} with fewer than 2 values of its first \
+                 argument",
+                BenchmarkResult::format_name(group, function, &args, threads)
+            );
+            continue;
+        }
+
+        let ns: Vec<u64> = fit_points.iter().map(|p| p.0).collect();
+        let real_times: Vec<f64> = fit_points.iter().map(|p| p.1).collect();
+        let cpu_times: Vec<f64> = fit_points.iter().map(|p| p.2).collect();
+        let fit = Fit::new(complexity, clock, &ns, &real_times, &cpu_times);
+        fits.push(
+            BenchmarkResult::fit(
+                group,
+                function,
+                others,
+                clock,
+                real_times.clone(),
+                cpu_times.clone(),
+                RunType::BigO(fit),
+            )
+            .with_threads(threads),
+        );
+        fits.push(
+            BenchmarkResult::fit(
+                group,
+                function,
+                others,
+                clock,
+                real_times,
+                cpu_times,
+                RunType::Rms(fit),
+            )
+            .with_threads(threads),
+        );
+    }
+    fits
+}
+
+fn range_generator(args: &[u64]) -> u64 {
+    args[0]
+}
+
+fn args_generator(args: &[u64]) -> Vec<u64> {
+    args.to_vec()
+}
+
+fn compose<T: 'static, U: 'static, G: Fn(T) -> U + 'static>(f: Generator<T>, g: G) -> Generator<U> {
+    Box::new(move |args: &[u64]| g(f(args)))
+}
+
+/// Returns the cartesian product of the values of every range, varying the last range fastest
+fn cartesian_product(ranges: &[ArgSpec]) -> Vec<Vec<u64>> {
+    let mut product = vec![Vec::new()];
+    for range in ranges {
+        let values = range.values();
+        product = product
+            .into_iter()
+            .flat_map(|args: Vec<u64>| {
+                values.iter().map(move |value| {
+                    let mut args = args.clone();
+                    args.push(*value);
+                    args
+                })
+            })
+            .collect();
+    }
+    product
 }
 
 /// The main Benchmark struct
 ///
 /// A benchmark consists of the following:
 ///
-/// - `name: &'static str` - will be prefixed to the output (see `run`)
-/// - One or more `bench: fn(&mut State<T>)` which are the actual benchmark. This is the actual
-/// benchmark which is timed
+/// - `name: String` - will be prefixed to the output (see `run`)
+/// - One or more `bench: Fn(&mut State<T>)` (functions or closures) which are the actual
+///   benchmark. This is the actual benchmark which is timed
 /// - A range which consists of a `lower_bound`, an `upper_bound`, and a `mul` factor. Will run the
-/// benchmark once for each `i in [lower_bound, upperbound]` such that `i` is initialized to
-/// `lower_bound` and gets multiplied by `mul`. Defaults to:
+///   benchmark once for each `i in [lower_bound, upperbound]` such that `i` is initialized to
+///   `lower_bound` and gets multiplied by `mul`. Defaults to:
 ///   - `lower_bound = 1`
 ///   - `upper_bound = 1 << 20`
 ///   - `mul = 2`
-/// - `generator: fn(u64) -> T` (optional) -  rather than passing `i` from above as input to the
-/// benchmark, T produced by this method is passed in instead. For each `i`, `gen(i)` is called
-/// once for each `bench` in this `benchmark`. The result of this is then cloned and passed into
-/// `bench` each time it is run.
+///
+///   Other sequences of arguments (linear steps, explicit lists, etc.) can be set with
+///   `with_arg_spec`. Benchmarks with more than one parameter can use several ranges (see
+///   `with_ranges`) or an explicit list of arguments (see `with_args`) instead.
+/// - `generator: Fn(u64) -> T` (optional) -  rather than passing `i` from above as input to the
+///   benchmark, T produced by this method is passed in instead. By default, `gen(i)` is called
+///   once for each `i` and its result is cloned for every run of every `bench`; see
+///   `with_input_strategy` to share it between runs instead, or to call `gen(i)` for every run.
+/// - `fixture: Fixture` (optional) - like a generator, but set up once per `i` and torn down
+///   once every `bench` is done with it (see `with_fixture`).
 ///
 /// # Examples
 ///
@@ -46,9 +557,9 @@ fn compose<T: 'static, U: 'static>(f: Box<Fn(u64) -> T>, g: fn(T) -> U) -> Box<F
 /// fn get_vec(n: u64) -> Vec<u64> {
 ///     let mut vec = Vec::new();
 ///     for i in 0..n {
-///         vec.push(i as u64);
+///         vec.push(i);
 ///     }
-///     return vec;
+///     vec
 /// }
 ///
 /// fn bm_vector_gen(state: &mut pew::State<Vec<u64>>) {
@@ -60,145 +571,1161 @@ fn compose<T: 'static, U: 'static>(f: Box<Fn(u64) -> T>, g: fn(T) -> U) -> Box<F
 /// }
 ///
 /// fn main() {
-///     Benchmark::with_name("range_bench")
+///     let results = Benchmark::with_name("range_bench")
 ///         .with_range(1 << 5, 1 << 5, 2)
 ///         .with_generator(get_vec)
 ///         .with_bench(pew_bench!(bm_vector_gen))
 ///         .run();
+///
+///     assert_eq!(results.len(), 1);
+///     assert_eq!(results[0].name(), "range_bench/bm_vector_gen/32");
 /// }
 /// ```
 ///
 /// This will output:
 ///
 /// ```txt
-/// Name,Time (ns)
-/// gen_bench/f/1024,103674
-/// gen_bench/f/4096,412499
-/// gen_bench/f/16384,1634809
-/// gen_bench/f/65536,7168879
-/// gen_bench/f/262144,27419824
-/// gen_bench/f/1048576,109010591
+/// Name,Time (ns),CPU (ns),Min (ns),Max (ns),Median (ns),Std Dev (ns),CV (%),P90 (ns),P99 (ns),Runs,Iterations,Bytes/s,Items/s
+/// range_bench/bm_vector_gen/32,309,301,262,1893,279,17,5.81,312,375,3321710,1,,
 /// ```
 ///
 /// See `examples/` for more examples.
-pub struct Benchmark<T: 'static + Clone> {
-    name: &'static str,
-    fns: Vec<(&'static str, fn(&mut State<T>))>,
-    range: (u64, u64, u64),
-    generator: Box<Fn(u64) -> T>,
+pub struct Benchmark<T: 'static> {
+    name: String,
+    fns: Vec<(String, BenchFn<T>)>,
+    ranges: Vec<ArgSpec>,
+    args: Vec<Vec<u64>>,
+    generator: Generator<T>,
+    reporter: Option<Box<dyn Reporter>>,
+    clock: Option<ClockSource>,
+    warmup_duration: Option<u64>,
+    warmup_runs: Option<u64>,
+    repetitions: Option<u64>,
+    complexity: Option<Complexity>,
+    bytes_processed: Option<ProcessedFn>,
+    items_processed: Option<ProcessedFn>,
+    input_strategy: InputStrategy,
+    input_kind: InputKind<T>,
+    threads: Option<Threads<T>>,
 }
 
 impl Benchmark<u64> {
     /// Generates a new benchmark with name
-    pub fn with_name(name: &'static str) -> Self {
+    pub fn with_name<S: Into<String>>(name: S) -> Self {
         Benchmark {
-            name,
+            name: name.into(),
             fns: Vec::new(),
-            range: (1, 1 << 20, 2),
+            ranges: vec![ArgSpec::geometric(1, 1 << 20, 2)],
+            args: Vec::new(),
             generator: Box::new(range_generator),
+            reporter: None,
+            clock: None,
+            warmup_duration: None,
+            warmup_runs: None,
+            repetitions: None,
+            complexity: None,
+            bytes_processed: None,
+            items_processed: None,
+            input_strategy: InputStrategy::Clone,
+            input_kind: InputKind::Generated(u64::clone),
+            threads: None,
         }
     }
+
+    /// Runs the benchmark with every combination of one value from each range
+    ///
+    /// Each range is a `(lower_bound, upper_bound, mul)` triple like the one of `with_range`.
+    /// The input of the benchmark becomes the vector of arguments (one per range), which can be
+    /// passed on to a generator. Results are named after all of their arguments, e.g.
+    /// `group/fn/1024/8`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no ranges, or if any range has a lower bound of 0 or a `mul` less than
+    /// 2 (see `ArgSpec::geometric`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn get_table(args: Vec<u64>) -> Vec<String> {
+    ///     let (size, key_len) = (args[0], args[1] as usize);
+    ///     (0..size).map(|i| format!("{:0>1$}", i, key_len)).collect()
+    /// }
+    ///
+    /// fn bm_sort(state: &mut pew::State<Vec<String>>) {
+    ///     let mut table = state.get_input();
+    ///     table.sort();
+    ///     pew::do_not_optimize(table);
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("table")
+    ///         .with_ranges(&[(64, 128, 2), (8, 16, 2)])
+    ///         .with_generator(get_table)
+    ///         .with_bench(pew_bench!(bm_sort))
+    ///         .run_with(&config);
+    ///
+    ///     let names: Vec<String> = results.iter().map(|r| r.name()).collect();
+    ///     assert_eq!(
+    ///         names,
+    ///         [
+    ///             "table/bm_sort/64/8",
+    ///             "table/bm_sort/64/16",
+    ///             "table/bm_sort/128/8",
+    ///             "table/bm_sort/128/16",
+    ///         ]
+    ///     );
+    /// }
+    /// ```
+    pub fn with_ranges(self, ranges: &[(u64, u64, u64)]) -> Benchmark<Vec<u64>> {
+        if ranges.is_empty() {
+            panic!("A benchmark needs at least one range");
+        }
+        let mut benchmark = self.convert(
+            |_| Box::new(args_generator),
+            InputKind::Generated(Vec::clone),
+        );
+        benchmark.ranges = ranges.iter().map(|range| ArgSpec::from(*range)).collect();
+        benchmark
+    }
+
+    /// Adds a set of arguments to run the benchmark with
+    ///
+    /// This can be called several times to add more sets. Once any arguments have been added, the
+    /// ranges are ignored and only the added arguments are run, in the order they were added. As
+    /// with `with_ranges`, the input of the benchmark becomes the vector of arguments.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `args` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::Benchmark;
+    ///
+    /// fn bm_choose(state: &mut pew::State<Vec<u64>>) {
+    ///     let args = state.get_input();
+    ///     let (n, k) = (args[0], args[1]);
+    ///     pew::do_not_optimize((n - k + 1..=n).product::<u64>() / (1..=k).product::<u64>());
+    /// }
+    ///
+    /// let benchmark = Benchmark::with_name("choose")
+    ///     .with_args(&[20, 2])
+    ///     .with_args(&[20, 10])
+    ///     .with_bench(("bm_choose", bm_choose));
+    /// ```
+    pub fn with_args(self, args: &[u64]) -> Benchmark<Vec<u64>> {
+        self.convert(
+            |_| Box::new(args_generator),
+            InputKind::Generated(Vec::clone),
+        )
+        .with_args(args)
+    }
+
+    /// Runs the benchmark with every combination of one value from each spec
+    ///
+    /// This is `with_ranges` for arguments that are not geometric sequences (see `ArgSpec`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no specs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{ArgSpec, Benchmark};
+    ///
+    /// fn bm_pow(state: &mut pew::State<Vec<u64>>) {
+    ///     let args = state.get_input();
+    ///     pew::do_not_optimize(args[0].pow(args[1] as u32));
+    /// }
+    ///
+    /// let benchmark = Benchmark::with_name("pow")
+    ///     .with_arg_specs(vec![ArgSpec::list(&[2, 3, 10]), ArgSpec::dense(1, 8)])
+    ///     .with_bench(("bm_pow", bm_pow));
+    /// ```
+    pub fn with_arg_specs(self, specs: Vec<ArgSpec>) -> Benchmark<Vec<u64>> {
+        if specs.is_empty() {
+            panic!("A benchmark needs at least one argument spec");
+        }
+        let mut benchmark = self.convert(
+            |_| Box::new(args_generator),
+            InputKind::Generated(Vec::clone),
+        );
+        benchmark.ranges = specs;
+        benchmark
+    }
+}
+
+impl Benchmark<Vec<u64>> {
+    /// Adds another set of arguments to run the benchmark with (see `Benchmark::<u64>::with_args`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `args` is empty.
+    ///
+    /// ```should_panic
+    /// use pew::Benchmark;
+    ///
+    /// Benchmark::with_name("empty").with_args(&[]);
+    /// ```
+    pub fn with_args(mut self, args: &[u64]) -> Self {
+        if args.is_empty() {
+            panic!("A set of arguments cannot be empty");
+        }
+        self.args.push(args.to_vec());
+        self
+    }
 }
 
-impl<T: Clone> Benchmark<T> {
-    /// Sets the `lower_bound` for this benchmark
+impl<T> Benchmark<T> {
+    /// Sets the `lower_bound` for this benchmark (of the first range, if there are several)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is 0 or greater than the upper bound (set the upper bound first to raise
+    /// both), or if the range is not geometric (see `with_arg_spec`).
     pub fn with_lower_bound(mut self, lb: u64) -> Self {
-        self.range.0 = lb;
+        let (_, ub, mul) = self.ranges[0].geometric_parts();
+        self.ranges[0] = ArgSpec::geometric(lb, ub, mul);
         self
     }
 
-    /// Sets the `upper_bound` for this benchmark
+    /// Sets the `upper_bound` for this benchmark (of the first range, if there are several)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ub` is less than the lower bound, or if the range is not geometric (see
+    /// `with_arg_spec`).
     pub fn with_upper_bound(mut self, ub: u64) -> Self {
-        self.range.1 = ub;
+        let (lb, _, mul) = self.ranges[0].geometric_parts();
+        self.ranges[0] = ArgSpec::geometric(lb, ub, mul);
         self
     }
 
-    /// Sets the `mul` for this benchmark
+    /// Sets the `mul` for this benchmark (of the first range, if there are several)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mul` is less than 2, or if the range is not geometric (see `with_arg_spec`).
     pub fn with_mul(mut self, mul: u64) -> Self {
-        self.range.2 = mul;
+        let (lb, ub, _) = self.ranges[0].geometric_parts();
+        self.ranges[0] = ArgSpec::geometric(lb, ub, mul);
         self
     }
 
-    /// Sets the entire range for this benchmark
+    /// Sets the entire range for this benchmark, replacing any ranges set with `with_ranges`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is 0 or greater than `ub`, or if `mul` is less than 2 (see
+    /// `ArgSpec::geometric`).
     pub fn with_range(mut self, lb: u64, ub: u64, mul: u64) -> Self {
-        self.range = (lb, ub, mul);
+        self.ranges = vec![ArgSpec::geometric(lb, ub, mul)];
+        self
+    }
+
+    /// Sets the values of the argument of this benchmark, replacing any ranges set with
+    /// `with_range` or `with_ranges`
+    ///
+    /// Unlike `with_range`, this is not limited to geometric sequences (see `ArgSpec`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{ArgSpec, Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_arg_spec(ArgSpec::linear(100, 300, 100))
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&config);
+    ///
+    ///     let names: Vec<String> = results.iter().map(|r| r.name()).collect();
+    ///     assert_eq!(names, ["sum/bm_sum/100", "sum/bm_sum/200", "sum/bm_sum/300"]);
+    /// }
+    /// ```
+    pub fn with_arg_spec<S: Into<ArgSpec>>(mut self, spec: S) -> Self {
+        self.ranges = vec![spec.into()];
         self
     }
 
     /// Sets a generator for this benchmark
     ///
-    /// Multiple generators can be specified, each of which will be `fn(T) -> U`. These will be
-    /// composed with the previous generators.
+    /// Multiple generators can be specified, each of which will be a `Fn(T) -> U` (a function or
+    /// a closure). These will be composed with the previous generators.
     ///
     /// Note that calling this function will wipe out all previously set `bench`es. Therefore, this
-    /// function should be called before calling `with_bench`.
-    pub fn with_generator<U: Clone>(self, gen: fn(T) -> U) -> Benchmark<U> {
+    /// function should be called before calling `with_bench`. The threads set with
+    /// `with_threads` are kept.
+    pub fn with_generator<U: Clone, G: Fn(T) -> U + 'static>(self, gen: G) -> Benchmark<U> {
+        self.convert(
+            |generator| compose(generator, gen),
+            InputKind::Generated(U::clone),
+        )
+    }
+
+    /// Uses a `Fixture` as the input of this benchmark, set up from the argument (or the output of
+    /// the last generator)
+    ///
+    /// Like `with_generator`, this wipes out all previously set `bench`es. The input strategy
+    /// (see `with_input_strategy`) is ignored, as every run uses the same fixture. See `Fixture`
+    /// for an example.
+    pub fn with_fixture<F: Fixture<T> + 'static>(self) -> Benchmark<F> {
+        self.convert(
+            |generator| compose(generator, F::setup),
+            InputKind::Fixture(F::reset, F::teardown),
+        )
+    }
+
+    /// Returns this benchmark with its generator replaced by `f(generator)` (and therefore
+    /// without any bench functions, but with the same thread counts)
+    fn convert<U, F>(self, f: F, input_kind: InputKind<U>) -> Benchmark<U>
+    where
+        F: FnOnce(Generator<T>) -> Generator<U>,
+    {
+        let generator = f(self.generator);
         Benchmark {
             name: self.name,
             fns: Vec::new(),
-            range: self.range,
-            generator: compose(self.generator, gen),
+            ranges: self.ranges,
+            args: self.args,
+            generator,
+            reporter: self.reporter,
+            clock: self.clock,
+            warmup_duration: self.warmup_duration,
+            warmup_runs: self.warmup_runs,
+            repetitions: self.repetitions,
+            complexity: self.complexity,
+            bytes_processed: self.bytes_processed,
+            items_processed: self.items_processed,
+            input_strategy: self.input_strategy,
+            input_kind,
+            threads: self.threads.map(|threads| Threads {
+                counts: threads.counts,
+                run: None,
+            }),
         }
     }
 
+    /// Sets the reporter that the results of this benchmark are passed to
+    ///
+    /// Overrides the reporter selected with `--format` (or `Config::with_format`).
+    pub fn with_reporter<R: Reporter + 'static>(mut self, reporter: R) -> Self {
+        self.reporter = Some(Box::new(reporter));
+        self
+    }
+
+    /// Sets the clock that decides when this benchmark has run for long enough
+    ///
+    /// Overrides the clock selected with `--clock` (or `Config::with_clock`). See `ClockSource`.
+    pub fn with_clock(mut self, clock: ClockSource) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Sets how each run gets its input (see `InputStrategy`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, InputStrategy};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<Vec<u64>>) {
+    ///     pew::do_not_optimize(state.input().iter().sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_secs(0));
+    ///     let strategies = [
+    ///         InputStrategy::Clone,
+    ///         InputStrategy::Shared,
+    ///         InputStrategy::Regenerate,
+    ///     ];
+    ///     for &strategy in &strategies {
+    ///         let generated = Rc::new(Cell::new(0));
+    ///         let counted = generated.clone();
+    ///         let results = Benchmark::with_name("sum")
+    ///             .with_range(1 << 16, 1 << 16, 2)
+    ///             .with_generator(move |n| {
+    ///                 counted.set(counted.get() + 1);
+    ///                 (0..n).collect::<Vec<u64>>()
+    ///             })
+    ///             .with_input_strategy(strategy)
+    ///             .with_bench(pew_bench!(bm_sum))
+    ///             .run_with(&config);
+    ///
+    ///         // Only regenerated inputs are generated again for every run
+    ///         let expected = match strategy {
+    ///             InputStrategy::Regenerate => results[0].runs,
+    ///             _ => 1,
+    ///         };
+    ///         assert_eq!(generated.get(), expected);
+    ///     }
+    /// }
+    /// ```
+    pub fn with_input_strategy(mut self, input_strategy: InputStrategy) -> Self {
+        self.input_strategy = input_strategy;
+        self
+    }
+
+    /// Runs each bench function for at least this long before measuring it
+    ///
+    /// Overrides the duration set with `--warmup_duration` (or `Config::with_warmup_duration`).
+    /// Warmup runs are executed exactly like measured runs, so they warm up caches, fault in the
+    /// pages of the input and train the branch predictor, but their times are discarded.
+    pub fn with_warmup_duration(mut self, warmup_duration: Duration) -> Self {
+        self.warmup_duration = Some(config::duration_ns(warmup_duration));
+        self
+    }
+
+    /// Runs each bench function at least this many times before measuring it
+    ///
+    /// Overrides the count set with `--warmup_runs` (or `Config::with_warmup_runs`). If a warmup
+    /// duration is set as well, warmup continues until both are satisfied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{Benchmark, Config};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::time::Duration;
+    ///
+    /// let calls = Rc::new(Cell::new(0));
+    /// let counted = calls.clone();
+    /// let config = Config::new()
+    ///     .with_min_duration(Duration::from_secs(0))
+    ///     .with_min_runs(8);
+    /// let results = Benchmark::with_name("warmup")
+    ///     .with_range(1, 1, 2)
+    ///     .with_warmup_runs(3)
+    ///     .with_bench(("bm_count", move |state: &mut pew::State<u64>| {
+    ///         counted.set(counted.get() + 1);
+    ///         state.counter("call", counted.get());
+    ///     }))
+    ///     .run_with(&config);
+    ///
+    /// // The first 3 calls warm up, and only the 8 calls after them are measured
+    /// assert_eq!(calls.get(), 11);
+    /// assert_eq!(results[0].runs, 8);
+    /// assert_eq!(results[0].real_samples.len(), 8);
+    /// assert_eq!(results[0].counters["call"].value, (4..=11).sum::<u64>() as f64);
+    /// ```
+    pub fn with_warmup_runs(mut self, warmup_runs: u64) -> Self {
+        self.warmup_runs = Some(warmup_runs);
+        self
+    }
+
+    /// Repeats each bench function and argument this many times. This will always be at least 1.
+    ///
+    /// Overrides the count set with `--repetitions` (or `Config::with_repetitions`). Every
+    /// repetition is measured and reported separately, followed by aggregates across all of them
+    /// (see `Aggregate`). Only the first repetition is warmed up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_range(64, 64, 2)
+    ///         .with_repetitions(3)
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&config);
+    ///
+    ///     let names: Vec<String> = results.iter().map(|r| r.name()).collect();
+    ///     assert_eq!(
+    ///         names,
+    ///         [
+    ///             "sum/bm_sum/64",
+    ///             "sum/bm_sum/64",
+    ///             "sum/bm_sum/64",
+    ///             "sum/bm_sum/64_mean",
+    ///             "sum/bm_sum/64_median",
+    ///             "sum/bm_sum/64_stddev",
+    ///             "sum/bm_sum/64_cv",
+    ///         ]
+    ///     );
+    /// }
+    /// ```
+    pub fn with_repetitions(mut self, repetitions: u64) -> Self {
+        self.repetitions = Some(cmp::max(repetitions, 1));
+        self
+    }
+
+    /// Fits the times of each bench function to an asymptotic complexity
+    ///
+    /// After every argument in the range has been run, the mean time of each argument is fitted
+    /// to `time = coefficient * f(n)` with least squares, where `n` is the argument. Each bench
+    /// function then gets two extra results: `_BigO`, with the fitted complexity and coefficient,
+    /// and `_RMS`, with the normalized RMS error of the fit (see `Fit`). `Complexity::Auto` picks
+    /// the complexity that fits best. An argument of 0 is left out of the fits of `lgN` and
+    /// `NlgN`, for which it is undefined.
+    ///
+    /// If there are several arguments (see `with_ranges`), `n` is the first one, and a separate
+    /// fit is made for every combination of the others. Their results are named after the other
+    /// arguments, with `N` in place of the first one, e.g. `group/fn/N/8_BigO`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Complexity, Config, RunType};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).map(pew::do_not_optimize).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_range(1 << 10, 1 << 14, 2)
+    ///         .with_complexity(Complexity::ON)
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&config);
+    ///
+    ///     assert_eq!(results[5].name(), "sum/bm_sum_BigO");
+    ///     match results[5].run_type {
+    ///         RunType::BigO(fit) => assert_eq!(fit.complexity, Complexity::ON),
+    ///         _ => panic!("Expected a BigO result"),
+    ///     }
+    ///     assert_eq!(results[6].name(), "sum/bm_sum_RMS");
+    /// }
+    /// ```
+    ///
+    /// Ranges may start at 0:
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{ArgSpec, Benchmark, Complexity, Config, RunType};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).map(pew::do_not_optimize).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     for complexity in &[Complexity::Auto, Complexity::ONLogN] {
+    ///         let results = Benchmark::with_name("sum")
+    ///             .with_arg_spec(ArgSpec::linear(0, 1000, 250))
+    ///             .with_complexity(*complexity)
+    ///             .with_bench(pew_bench!(bm_sum))
+    ///             .run_with(&config);
+    ///
+    ///         match results[5].run_type {
+    ///             RunType::BigO(fit) => assert!(fit.real_coefficient.is_finite()),
+    ///             _ => panic!("Expected a BigO result"),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// With several arguments:
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Complexity, Config, RunType};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<Vec<u64>>) {
+    ///     let args = state.get_input();
+    ///     let (n, stride) = (args[0], args[1] as usize);
+    ///     pew::do_not_optimize((0..n).step_by(stride).map(pew::do_not_optimize).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_ranges(&[(1 << 10, 1 << 12, 2), (1, 2, 2)])
+    ///         .with_complexity(Complexity::ON)
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&config);
+    ///
+    ///     let fits: Vec<String> = results[6..].iter().map(|r| r.name()).collect();
+    ///     assert_eq!(
+    ///         fits,
+    ///         [
+    ///             "sum/bm_sum/N/1_BigO",
+    ///             "sum/bm_sum/N/1_RMS",
+    ///             "sum/bm_sum/N/2_BigO",
+    ///             "sum/bm_sum/N/2_RMS",
+    ///         ]
+    ///     );
+    ///     // Every fit only has the 3 values of the first argument with the same stride
+    ///     for fit in &results[6..] {
+    ///         assert_eq!(fit.runs, 3);
+    ///         match fit.run_type {
+    ///             RunType::BigO(fit) | RunType::Rms(fit) => {
+    ///                 assert_eq!(fit.complexity, Complexity::ON)
+    ///             }
+    ///             _ => panic!("Expected a fit"),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn with_complexity(mut self, complexity: Complexity) -> Self {
+        self.complexity = Some(complexity);
+        self
+    }
+
+    /// Sets the number of bytes processed by each iteration of every bench function, as a function
+    /// of the argument (i.e. `i` from the range, or the first argument if there are several)
+    ///
+    /// The results will include the number of bytes processed per second. A bench function can
+    /// override this with `State::set_bytes_processed`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn get_vec(n: u64) -> Vec<u64> {
+    ///     (0..n).collect()
+    /// }
+    ///
+    /// fn bm_clone(state: &mut pew::State<Vec<u64>>) {
+    ///     let vec = state.get_input();
+    ///     pew::do_not_optimize(vec.clone());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("clone")
+    ///         .with_range(1 << 10, 1 << 10, 2)
+    ///         .with_bytes_processed(|n| n * 8)
+    ///         .with_generator(get_vec)
+    ///         .with_bench(pew_bench!(bm_clone))
+    ///         .run_with(&config);
+    ///
+    ///     assert!(results[0].bytes_per_second.unwrap() > 0.0);
+    ///     assert_eq!(results[0].items_per_second, None);
+    /// }
+    /// ```
+    pub fn with_bytes_processed<F: Fn(u64) -> u64 + 'static>(mut self, bytes: F) -> Self {
+        self.bytes_processed = Some(Box::new(bytes));
+        self
+    }
+
+    /// Sets the number of items processed by each iteration of every bench function, as a function
+    /// of the argument (i.e. `i` from the range, or the first argument if there are several)
+    ///
+    /// The results will include the number of items processed per second. A bench function can
+    /// override this with `State::set_items_processed`.
+    pub fn with_items_processed<F: Fn(u64) -> u64 + 'static>(mut self, items: F) -> Self {
+        self.items_processed = Some(Box::new(items));
+        self
+    }
+
     /// Specifies a benchmark method
     ///
     /// This must be called one or more times before calling `run`. All functions in this suite
     /// will have the same range and generator(s).
     ///
     /// This accepts a tuple with the benchmark name and the function. If the function is an ident
-    /// and you want the benchmark name to match the function, use `pew_bench!`.
-    pub fn with_bench(mut self, t: (&'static str, fn(&mut State<T>))) -> Self {
-        self.fns.push(t);
+    /// and you want the benchmark name to match the function, use `pew_bench!`. The function can
+    /// also be a closure, e.g. to capture data that is shared by every run, and the name can be
+    /// built at runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{Benchmark, Config};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let table: Arc<Vec<u64>> = Arc::new((0..1024).map(|i| i * i).collect());
+    /// let mut benchmark = Benchmark::with_name("lookup").with_range(8, 8, 2);
+    /// for stride in &[1, 16] {
+    ///     let table = table.clone();
+    ///     let stride = *stride;
+    ///     let bench = move |state: &mut pew::State<u64>| {
+    ///         let n = state.get_input() as usize;
+    ///         pew::do_not_optimize(table.iter().step_by(stride).take(n).sum::<u64>());
+    ///     };
+    ///     benchmark = benchmark.with_bench((format!("stride_{}", stride), bench));
+    /// }
+    ///
+    /// let config = Config::new().with_min_duration(Duration::from_millis(10));
+    /// let results = benchmark.run_with(&config);
+    /// assert_eq!(results[0].name(), "lookup/stride_1/8");
+    /// assert_eq!(results[1].name(), "lookup/stride_16/8");
+    /// ```
+    ///
+    /// Closures do not need to be `Sync` unless they run on several threads (see
+    /// `with_threaded_bench`), so they can capture e.g. an `Rc`:
+    ///
+    /// ```
+    /// use pew::{Benchmark, Config};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::time::Duration;
+    ///
+    /// let calls = Rc::new(Cell::new(0));
+    /// let counted = calls.clone();
+    /// let results = Benchmark::with_name("count")
+    ///     .with_range(1, 1, 2)
+    ///     .with_bench(("calls", move |_: &mut pew::State<u64>| counted.set(counted.get() + 1)))
+    ///     .run_with(&Config::new().with_min_duration(Duration::from_secs(0)));
+    ///
+    /// assert_eq!(calls.get(), results[0].runs);
+    /// ```
+    pub fn with_bench<S, F>(mut self, t: (S, F)) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&mut State<T>) + 'static,
+    {
+        self.fns.push((t.0.into(), BenchFn::Local(Box::new(t.1))));
         self
     }
 
     /// Runs the benchmark
     ///
-    /// Prints the result as a csv with the following format:
+    /// Returns a `BenchmarkResult` for every bench function and argument that was run (i.e. that
+    /// matched `--filter`), in the order they were run. If the benchmark is repeated (see
+    /// `with_repetitions`), there is one for every repetition followed by one for every
+    /// `Aggregate`.
     ///
-    /// - Header which will be exactly `Name,Time(ns)` (this will be printed once for the whole
-    /// program, not once per call to run).
+    /// Every run of a bench function is recorded as a separate sample. The results are passed to
+    /// the reporter set with `with_reporter` or, if there is none, the one selected with
+    /// `--format`. The default reporter prints the result as a csv with the following format:
+    ///
+    /// - Header which will be exactly `Name,Time (ns),CPU (ns),Min (ns),Max (ns),Median (ns),Std
+    ///   Dev (ns),CV (%),P90 (ns),P99 (ns),Runs,Iterations,Bytes/s,Items/s` (this will be printed
+    ///   once for the whole program, not once per call to run).
     /// - Rows where
     ///   - `name` will be a slash separated concatenation of the benchmark name, the function
-    ///   name, and i
-    ///   - `time` will be the time in nanoseconds for running the benchmark
+    ///     name, and i
+    ///   - `time` will be the mean wall clock time in nanoseconds for running the benchmark (or a
+    ///     single iteration of it, see `State::iter`)
+    ///   - `cpu` will be the mean CPU time in nanoseconds for running the benchmark (or a single
+    ///     iteration of it)
+    ///   - the next columns describe the spread of the samples of the selected `ClockSource`
+    ///     (see `Statistics`)
+    ///   - `runs` will be the number of times the bench function was run
+    ///   - `iterations` will be the number of iterations in each run
+    ///   - `bytes/s` and `items/s` will be the throughput if it was set (see
+    ///     `with_bytes_processed`), and empty otherwise
+    ///
+    /// Aggregate rows only have the `time` and `cpu` columns (the aggregate of the mean of every
+    /// repetition) and `runs` (the number of repetitions). `_cv` rows are percentages rather than
+    /// nanoseconds.
+    ///
+    /// With `--save-baseline NAME` the results are also saved as the baseline `NAME`, and with
+    /// `--baseline NAME` every result is compared to the same benchmark in that baseline (see
+    /// `Config::with_baseline`). With `--fail-if-slower PERCENT`, the process exits with an error
+    /// once every suite has run if any benchmark got slower by more than `PERCENT` (see
+    /// `Config::with_fail_if_slower`).
+    ///
+    /// The flags of libtest that `cargo bench` passes to benchmarks with `harness = false` are
+    /// accepted too: a positional `FILTER` only runs benchmarks whose name contains it (or is
+    /// exactly it with `--exact`), `--list` prints the name of every benchmark instead of running
+    /// it, `--test` runs every benchmark only briefly, and `--bench` and `--nocapture` are
+    /// ignored. Use `--format libtest` to print the results like libtest (see
+    /// `LibtestReporter`).
+    ///
+    /// The config is parsed from the command line arguments of the process the first time any
+    /// benchmark is run (see `Config::from_args`). Use `run_with` to avoid this.
     ///
     /// # Panics
     ///
-    /// Panics if no bench methods are specified.
-    pub fn run(self) {
-        if self.fns.len() == 0 {
+    /// Panics like `run_with`, e.g. if no bench methods are specified.
+    pub fn run(self) -> Vec<BenchmarkResult> {
+        let config = Config::get();
+        if config.fail_if_slower.is_some() {
+            baseline::report_regressions_at_exit();
+        }
+        self.run_with(config)
+    }
+
+    /// Runs the benchmark with an explicit config
+    ///
+    /// This behaves exactly like `run` except that it never looks at the command line arguments
+    /// of the process, and never makes the process exit: the regressions found with
+    /// `Config::with_fail_if_slower` are only reported (and make the process exit) once
+    /// `pew::finish` is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new()
+    ///         .with_filter("/64$")
+    ///         .with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_range(16, 64, 2)
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&config);
+    ///
+    ///     assert_eq!(results.len(), 1);
+    ///     assert_eq!(results[0].args, [64]);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no bench methods are specified, if a fixture would be shared by more than one
+    /// thread or a bench function was not added with `with_threaded_bench` (see `with_threads`),
+    /// or if `Config::with_fail_if_slower` is set without a baseline or the baseline cannot be
+    /// read.
+    pub fn run_with(self, config: &Config) -> Vec<BenchmarkResult> {
+        if self.fns.is_empty() {
             panic!("Cannot call run on an empty benchmark");
         }
+        // Comparing to nothing would let every regression through
+        if config.fail_if_slower.is_some() && config.baseline.is_none() {
+            panic!("fail_if_slower needs a baseline to compare to, set one with with_baseline");
+        }
+        let thread_counts: Vec<Option<u64>> = match self.threads {
+            Some(ref threads) => threads.counts.iter().map(|count| Some(*count)).collect(),
+            None => vec![None],
+        };
+        if let InputKind::Fixture(..) = self.input_kind {
+            if thread_counts.iter().any(|count| count.unwrap_or(1) > 1) {
+                panic!("A fixture cannot be shared by more than one thread");
+            }
+        }
+        if self.threads.is_some() {
+            if let Some((name, _)) = self.fns.iter().find(|(_, f)| f.get_sync().is_none()) {
+                panic!(
+                    "{} cannot run on several threads, add it with with_threaded_bench",
+                    name
+                );
+            }
+        }
+        let all_args = if self.args.is_empty() {
+            cartesian_product(&self.ranges)
+        } else {
+            self.args.clone()
+        };
+        if config.list {
+            for args in &all_args {
+                for (name, _) in &self.fns {
+                    for threads in &thread_counts {
+                        let bm_name =
+                            BenchmarkResult::format_name(&self.name, name, args, *threads);
+                        if should_run_bm(config, &bm_name) {
+                            println!("{}: bench", bm_name);
+                        }
+                    }
+                }
+            }
+            return Vec::new();
+        }
+
+        let mut reporter = match self.reporter {
+            Some(reporter) => reporter,
+            None => config.format.reporter(),
+        };
+        let clock = self.clock.unwrap_or(config.clock);
+        let calibration = Calibration::get(clock);
+        reporter.begin_suite(&Context {
+            name: self.name.clone(),
+            clock,
+            calibration,
+            overhead_subtracted: config.subtract_overhead,
+        });
+
+        let warmup = Warmup {
+            duration: self.warmup_duration.unwrap_or(config.warmup_duration),
+            runs: self.warmup_runs.unwrap_or(config.warmup_runs),
+        };
+        let baseline = config.baseline.as_ref().and_then(|name| {
+            match Baseline::load(&config.baseline_dir, name) {
+                Ok(baseline) => Some(baseline),
+                // Comparing to nothing would let every regression through
+                Err(e) if config.fail_if_slower.is_some() => {
+                    panic!("Cannot read baseline {}: {}", name, e)
+                }
+                Err(e) => {
+                    eprintln!("Warning: cannot read baseline {}: {}", name, e);
+                    None
+                }
+            }
+        });
+        let repetitions = self.repetitions.unwrap_or(config.repetitions);
+        let report_repetitions = repetitions == 1 || !config.report_aggregates_only;
+        let mut results = Vec::new();
 
-        let (lb, ub, mul) = self.range;
-        let mut i = lb;
         let gen = &self.generator;
-        while i <= ub {
-            let input = gen(i);
+        for args in &all_args {
+            let mut inputs = match self.input_kind {
+                InputKind::Fixture(reset, teardown) => {
+                    Inputs::Fixture(Some(gen(args)), reset, teardown)
+                }
+                InputKind::Generated(clone) => match self.input_strategy {
+                    InputStrategy::Clone => Inputs::Cloned(gen(args), clone),
+                    InputStrategy::Shared => Inputs::Shared(Arc::new(gen(args)), clone),
+                    InputStrategy::Regenerate => Inputs::Regenerated(gen, args),
+                },
+            };
+            let processed = Processed {
+                bytes: self.bytes_processed.as_ref().map(|f| f(args[0])),
+                items: self.items_processed.as_ref().map(|f| f(args[0])),
+            };
             for (name, f) in &self.fns {
-                let bm_name = format!("{}/{}/{}", self.name, name, i);
-                if should_run_bm(&bm_name) {
-                    let mut runs = 0;
-                    let mut total_duration = 0;
-                    while runs < Config::get().min_runs as u64
-                        || total_duration < Config::get().min_duration
-                    {
-                        let mut state = State::new(input.clone());
-                        f(&mut state);
-                        total_duration += state.finish();
-                        runs += 1;
+                for threads in &thread_counts {
+                    let bm_name = BenchmarkResult::format_name(&self.name, name, args, *threads);
+                    if !should_run_bm(config, &bm_name) {
+                        continue;
                     }
-
-                    HEADER.call_once(|| {
-                        println!("Name,Time (ns)");
+                    let run_threads = self.threads.as_ref().and_then(|t| {
+                        threads.map(|count| (t.run.expect("with_threaded_bench sets it"), count))
                     });
 
-                    println!("{},{}", bm_name, total_duration / runs);
+                    let mut runs = Vec::new();
+                    for repetition in 0..repetitions {
+                        let no_warmup = Warmup::default();
+                        let warmup = if repetition == 0 { &warmup } else { &no_warmup };
+                        let measurement = measure(
+                            config,
+                            &calibration,
+                            warmup,
+                            &processed,
+                            f,
+                            &mut inputs,
+                            run_threads,
+                        );
+                        if config.verbose {
+                            eprintln!(
+                                "{} (repetition {}/{}): {} warmup runs ({} ns), {} runs of {} \
+                                 iterations",
+                                bm_name,
+                                repetition + 1,
+                                repetitions,
+                                measurement.warmup_runs,
+                                measurement.warmup_duration,
+                                measurement.samples.len(),
+                                measurement.iterations
+                            );
+                        }
+                        if repetition == 0 {
+                            check_overhead(&bm_name, &calibration, &measurement.samples);
+                        }
+                        let result = BenchmarkResult::new(
+                            &self.name,
+                            name,
+                            args,
+                            clock,
+                            measurement.iterations,
+                            &measurement.samples,
+                        )
+                        .with_threads(*threads)
+                        .with_repetition(repetition, repetitions)
+                        .with_processed(measurement.bytes_processed, measurement.items_processed)
+                        .with_counters(measurement.counters)
+                        .with_baseline(baseline.as_ref());
+                        if report_repetitions {
+                            reporter.benchmark(&result);
+                        }
+                        runs.push(result);
+                    }
+
+                    let mut aggregates = Vec::new();
+                    if repetitions > 1 {
+                        for aggregate in &Aggregate::ALL {
+                            let result = BenchmarkResult::aggregate(&runs, *aggregate);
+                            reporter.benchmark(&result);
+                            aggregates.push(result);
+                        }
+                    }
+                    results.append(&mut runs);
+                    results.append(&mut aggregates);
                 }
             }
-            i *= mul;
         }
+
+        if let Some(complexity) = self.complexity {
+            for (name, _) in &self.fns {
+                for threads in &thread_counts {
+                    let fits =
+                        fit_complexity(&self.name, name, *threads, clock, complexity, &results);
+                    for result in fits {
+                        reporter.benchmark(&result);
+                        results.push(result);
+                    }
+                }
+            }
+        }
+        reporter.end_suite();
+        if let Some(ref name) = config.save_baseline {
+            baseline::save(&config.baseline_dir, name, &results);
+        }
+        if let Some(percent) = config.fail_if_slower {
+            baseline::record_regressions(&results, percent);
+        }
+        results
+    }
+}
+
+impl<T: Send + Sync> Benchmark<T> {
+    /// Runs every bench function on `threads` threads at once
+    ///
+    /// The threads are started together behind a barrier, and each of them calls the bench
+    /// function with a `State` of its own (see `State::thread_index`). Every thread gets its own
+    /// input according to the input strategy (see `with_input_strategy`). The wall clock time of
+    /// a run is measured once for the whole group of threads, from the first of them starting
+    /// until the last of them is done (pausing a thread does not pause it), and its CPU time is
+    /// the sum of the CPU times of the threads. Results are named after the number of threads,
+    /// e.g. `group/fn/64/threads:4`.
+    ///
+    /// Threads are started for every run, so bench functions should use `State::iter` to make
+    /// each run long enough that this does not matter. Bench functions must be added with
+    /// `with_threaded_bench` rather than `with_bench`, since they are shared by the threads, and
+    /// after the last call to `with_generator` (or `with_fixture`), which keeps the thread counts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, State};
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    ///
+    /// static COUNTER: AtomicU64 = AtomicU64::new(0);
+    ///
+    /// fn bm_fetch_add(state: &mut State<u64>) {
+    ///     assert!(state.thread_index() < state.thread_count());
+    ///     for _ in state.iter() {
+    ///         COUNTER.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("atomic")
+    ///         .with_range(1, 1, 2)
+    ///         .with_threads(4)
+    ///         .with_threaded_bench(pew_bench!(bm_fetch_add))
+    ///         .run_with(&config);
+    ///
+    ///     assert_eq!(results[0].name(), "atomic/bm_fetch_add/1/threads:4");
+    ///     assert_eq!(results[0].threads, Some(4));
+    ///
+    ///     // Unlike the bench functions, the thread counts outlive a generator
+    ///     let results = Benchmark::with_name("atomic")
+    ///         .with_range(1, 1, 2)
+    ///         .with_threads(2)
+    ///         .with_generator(|n| n + 1)
+    ///         .with_threaded_bench(pew_bench!(bm_fetch_add))
+    ///         .run_with(&config);
+    ///
+    ///     assert_eq!(results[0].threads, Some(2));
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is 0.
+    pub fn with_threads(self, threads: u64) -> Self {
+        self.with_thread_counts(vec![threads])
+    }
+
+    /// Adds a bench function that can be run on several threads at once (see `with_threads`)
+    ///
+    /// This is `with_bench` for bench functions that are `Sync`, which every function and most
+    /// closures are. Benchmarks without threads can use either.
+    pub fn with_threaded_bench<S, F>(mut self, t: (S, F)) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&mut State<T>) + Sync + 'static,
+    {
+        if let Some(ref mut threads) = self.threads {
+            threads.run = Some(run_threads);
+        }
+        self.fns.push((t.0.into(), BenchFn::Sync(Box::new(t.1))));
+        self
+    }
+
+    /// Runs every bench function on `lb` threads, then twice as many, and so on up to and
+    /// including `ub` threads (see `with_threads`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lb` is 0 or greater than `ub`.
+    ///
+    /// ```should_panic
+    /// use pew::Benchmark;
+    ///
+    /// Benchmark::with_name("atomic").with_thread_range(0, 4);
+    /// ```
+    pub fn with_thread_range(self, lb: u64, ub: u64) -> Self {
+        if lb == 0 {
+            panic!("Cannot run a benchmark on 0 threads");
+        }
+        if lb > ub {
+            panic!("The lower bound of a thread range cannot exceed its upper bound");
+        }
+        let mut counts = Vec::new();
+        let mut threads = Some(lb);
+        while let Some(count) = threads.filter(|count| *count < ub) {
+            counts.push(count);
+            threads = count.checked_mul(2);
+        }
+        counts.push(ub);
+        self.with_thread_counts(counts)
+    }
+
+    fn with_thread_counts(mut self, counts: Vec<u64>) -> Self {
+        if counts.contains(&0) {
+            panic!("Cannot run a benchmark on 0 threads");
+        }
+        self.threads = Some(Threads {
+            counts,
+            run: Some(run_threads),
+        });
+        self
     }
 }
@@ -0,0 +1,287 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Compares the output of two runs of a benchmark.
+//!
+//! Both files can be the output of `--format csv`, `--format json` or
+//! `--format google_json`, or a baseline saved with `--save-baseline`. Benchmarks are matched by name
+//! (`group/fn/arg`) and the mean time of each is compared:
+//!
+//! ```
+//! $ pew_compare old.json new.json
+//! Benchmark                        Old (ns)  New (ns)  Delta (ns)   Ratio  P-value
+//! range_bench/bm_vector_range/1024    27486     24711       -2775  0.8990   0.0000
+//! gen_bench/bm_vector_gen/1024        26869     27012        +143  1.0053   0.4817
+//! ```
+//!
+//! `Ratio` is the new time divided by the old one. `P-value` is the p-value of
+//! Welch's t-test, which is only available when both files have the samples of
+//! every run (i.e. json output or baselines), and is empty otherwise.
+//!
+//! The wall clock time is compared unless `--cpu` is passed. Repetitions of a
+//! benchmark are pooled, while aggregates, complexity fits and benchmarks that
+//! are only in one of the files are skipped. A warning tells how many rows of a
+//! csv file were skipped.
+
+extern crate clap;
+extern crate pew;
+extern crate serde_json;
+
+use clap::{App, Arg};
+use pew::{Comparison, Statistics};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::process;
+
+/// The times of every run of a benchmark in one file
+#[derive(Default)]
+struct Times {
+    /// The mean time of every row of the benchmark
+    means: Vec<f64>,
+    /// The time of every run of the benchmark, if the file has them
+    samples: Vec<f64>,
+}
+
+impl Times {
+    fn is_empty(&self) -> bool {
+        self.means.is_empty() && self.samples.is_empty()
+    }
+
+    /// Returns the mean time, which `read` makes sure there is
+    fn mean(&self) -> f64 {
+        if self.samples.is_empty() {
+            Statistics::from_samples(&self.means).mean
+        } else {
+            Statistics::from_samples(&self.samples).mean
+        }
+    }
+}
+
+/// The benchmarks of a file in the order they first appear
+#[derive(Default)]
+struct Benchmarks {
+    names: Vec<String>,
+    times: HashMap<String, Times>,
+}
+
+impl Benchmarks {
+    fn entry(&mut self, name: &str) -> &mut Times {
+        if !self.times.contains_key(name) {
+            self.names.push(name.to_string());
+        }
+        self.times.entry(name.to_string()).or_default()
+    }
+}
+
+/// Returns the benchmarks of a csv file along with the number of rows that were skipped
+fn parse_csv(contents: &str, cpu: bool) -> (Benchmarks, usize) {
+    let mut benchmarks = Benchmarks::default();
+    let mut skipped = 0;
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        let split: Vec<&str> = line.split(',').collect();
+        if split[0] == "Name" {
+            continue;
+        }
+        // Aggregates and complexity fits leave `Iterations` empty
+        if split.len() < 12 || split[11].is_empty() {
+            skipped += 1;
+            continue;
+        }
+        let time = if cpu { split[2] } else { split[1] };
+        match time.parse::<f64>() {
+            Ok(time) => benchmarks.entry(split[0]).means.push(time),
+            Err(_) => skipped += 1,
+        }
+    }
+    (benchmarks, skipped)
+}
+
+/// Returns the time of an entry of `--format google_json` in ns
+fn google_json_time(benchmark: &Value, cpu: bool) -> io::Result<f64> {
+    let time = benchmark[if cpu { "cpu_time" } else { "real_time" }].as_f64();
+    let scale = match benchmark["time_unit"].as_str() {
+        Some("ns") | None => Some(1.0),
+        Some("us") => Some(1e3),
+        Some("ms") => Some(1e6),
+        Some("s") => Some(1e9),
+        Some(_) => None,
+    };
+    match (time, scale) {
+        (Some(time), Some(scale)) => Ok(time * scale),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid time of {}", benchmark["name"]),
+        )),
+    }
+}
+
+fn parse_json(contents: &str, cpu: bool) -> io::Result<Benchmarks> {
+    let samples_key = if cpu { "cpu_samples" } else { "real_samples" };
+    let samples = |benchmark: &Value| -> Vec<f64> {
+        benchmark[samples_key].as_array().map_or(Vec::new(), |s| {
+            s.iter().filter_map(|s| s.as_f64()).collect()
+        })
+    };
+
+    let mut benchmarks = Benchmarks::default();
+    for document in serde_json::Deserializer::from_str(contents).into_iter::<Value>() {
+        let document = document.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match document["benchmarks"] {
+            // A baseline saved with `--save-baseline`
+            Value::Object(ref saved) => {
+                for (name, benchmark) in saved {
+                    benchmarks.entry(name).samples.extend(samples(benchmark));
+                }
+            }
+            // A suite printed by `--format json` or `--format google_json`
+            Value::Array(ref reported) => {
+                for benchmark in reported {
+                    let name = benchmark["name"].as_str().unwrap_or_default();
+                    match benchmark["run_type"].as_str() {
+                        Some("repetition") => {
+                            benchmarks.entry(name).samples.extend(samples(benchmark))
+                        }
+                        // google_json only has the mean time of every repetition
+                        Some("iteration") => {
+                            let time = google_json_time(benchmark, cpu)?;
+                            benchmarks.entry(name).means.push(time);
+                        }
+                        Some("aggregate") => {}
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("unknown run_type of {}", name),
+                            ))
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing \"benchmarks\"",
+                ))
+            }
+        }
+    }
+    Ok(benchmarks)
+}
+
+fn read(path: &str, cpu: bool) -> io::Result<Benchmarks> {
+    let contents = fs::read_to_string(path)?;
+    let benchmarks = if contents.trim_start().starts_with('{') {
+        parse_json(&contents, cpu)?
+    } else {
+        let (benchmarks, skipped) = parse_csv(&contents, cpu);
+        if skipped > 0 {
+            eprintln!(
+                "Warning: skipped {} rows of {} that are not runs of a benchmark (e.g. \
+                 aggregates and complexity fits)",
+                skipped, path
+            );
+        }
+        benchmarks
+    };
+    let empty = benchmarks
+        .names
+        .iter()
+        .find(|name| benchmarks.times[*name].is_empty());
+    if let Some(name) = empty {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no times", name),
+        ));
+    }
+    Ok(benchmarks)
+}
+
+fn main() {
+    let app_flags = App::new("pew-compare")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Compares the output of two runs of a pew benchmark")
+        .arg(
+            Arg::with_name("old")
+                .value_name("OLD")
+                .help("The output of the run to compare against")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("new")
+                .value_name("NEW")
+                .help("The output of the run to compare")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("cpu")
+                .long("cpu")
+                .help("Compare the CPU time rather than the wall clock time"),
+        )
+        .get_matches();
+
+    let cpu = app_flags.is_present("cpu");
+    let read = |arg| {
+        let path = app_flags.value_of(arg).unwrap();
+        read(path, cpu).unwrap_or_else(|why| {
+            eprintln!("ERROR: Could not read {}: {}", path, why);
+            process::exit(1);
+        })
+    };
+    let (old, new) = (read("old"), read("new"));
+
+    let mut rows = vec![vec![
+        "Benchmark".to_string(),
+        "Old (ns)".to_string(),
+        "New (ns)".to_string(),
+        "Delta (ns)".to_string(),
+        "Ratio".to_string(),
+        "P-value".to_string(),
+    ]];
+    for name in &new.names {
+        let new_times = &new.times[name];
+        let old_times = match old.times.get(name) {
+            Some(times) => times,
+            None => continue,
+        };
+        let (old_mean, new_mean) = (old_times.mean(), new_times.mean());
+        let p_value = Comparison::from_samples("", &old_times.samples, &new_times.samples)
+            .map_or(String::new(), |c| format!("{:.4}", c.p_value));
+        rows.push(vec![
+            name.clone(),
+            format!("{:.0}", old_mean),
+            format!("{:.0}", new_mean),
+            format!("{:+.0}", new_mean - old_mean),
+            format!("{:.4}", new_mean / old_mean),
+            p_value,
+        ]);
+    }
+
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in &rows {
+        let mut line = format!("{:<width$}", row[0], width = widths[0]);
+        for (cell, width) in row[1..].iter().zip(&widths[1..]) {
+            line.push_str(&format!("  {:>width$}", cell, width = width));
+        }
+        println!("{}", line.trim_end());
+    }
+}
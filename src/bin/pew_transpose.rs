@@ -17,32 +17,37 @@
 //! Transposes the output of a benchmark.
 //!
 //! This assumes that you are running multiple benchmarks (`RANGE or
-//! `GENRANGE`) with the same range. It will then transform the default
-//! output:
+//! `GENRANGE`) with the same range. Only the mean (`Time (ns)`) column is
+//! kept. It will then transform the default output:
 //!
 //! ```
-//! Name,Time (ns)
-//! bm_vector_range/1024,102541
-//! bm_vector_range/4096,423289
-//! bm_vector_gen/1024,102316
-//! bm_vector_gen/4096,416523
+//! Name,Time (ns),Min (ns),...
+//! range_bench/bm_vector_range/1024,102541
+//! range_bench/bm_vector_range/4096,423289
+//! gen_bench/bm_vector_gen/1024,102316
+//! gen_bench/bm_vector_gen/4096,416523
 //! ```
 //!
 //! into:
 //!
 //! ```
-//! Size,bm_vector_range,bm_vector_gen
+//! Size,range_bench/bm_vector_range,gen_bench/bm_vector_gen
 //! 1024,105974,106845
 //! 4096,418835,409143
 //! ```
+//!
+//! If the benchmarks were repeated, only the `_mean` aggregate of each is kept.
+//!
+//! Benchmarks with several arguments (e.g. `group/bm_table/1024/8`) use the first
+//! argument as the size, and keep the other arguments in the column name
+//! (`group/bm_table/8`).
 
 #[macro_use]
 extern crate lazy_static;
 extern crate clap;
 
 use clap::{App, Arg, ArgMatches};
-use std::collections::BTreeMap;
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{self, BufRead, Write};
 use std::path::Path;
@@ -66,68 +71,83 @@ lazy_static! {
 
 fn parse_line(line: &str) -> Option<(String, &str, &str)> {
     let split: Vec<&str> = line.split(',').collect();
-    if split.len() != 2 {
+    if split.len() < 2 {
         return None;
     }
     let name = split[0];
     let time = split[1];
 
+    let name = name.strip_suffix("_mean").unwrap_or(name);
     let split: Vec<&str> = name.split('/').collect();
-    if split.len() != 3 {
+    // Arguments never contain an underscore, so one in the last segment is the suffix of another
+    // aggregate (e.g. `_median`)
+    if split.len() < 3 || split[split.len() - 1].contains('_') {
         return None;
     }
     let global_name = split[0];
     let bench_name = split[1];
     let size = split[2];
-    return Some((format!("{}/{}", global_name, bench_name), size, time));
+    let mut name = format!("{}/{}", global_name, bench_name);
+    for arg in &split[3..] {
+        name.push('/');
+        name.push_str(arg);
+    }
+    Some((name, size, time))
 }
 
 fn main() {
     let stdin = io::stdin();
-    let mut results: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut results: BTreeMap<usize, HashMap<String, String>> = BTreeMap::new();
     let mut names = Vec::new();
     for line in stdin.lock().lines() {
         let line = line.unwrap();
 
-        if let Some(_) = APP_FLAGS.value_of("file") {
+        if APP_FLAGS.value_of("file").is_some() {
             println!("{}", line);
         }
 
         if let Some((name, size, time)) = parse_line(&line) {
             if !names.contains(&name) {
-                names.push(name);
+                names.push(name.clone());
             }
 
+            // Repetitions of the same benchmark are replaced by the `_mean` that follows them
             let size = size.to_string().parse::<usize>().unwrap();
-            if let None = results.get(&size) {
-                results.insert(size, vec![time.to_string()]);
-            } else {
-                let vec = results.get_mut(&size).unwrap();
-                vec.push(time.to_string());
-            }
+            results
+                .entry(size)
+                .or_default()
+                .insert(name, time.to_string());
         }
     }
 
+    let row = |times: &HashMap<String, String>| {
+        let times: Vec<&str> = names
+            .iter()
+            .map(|name| times.get(name).map_or("", |t| t.as_str()))
+            .collect();
+        times.join(",")
+    };
+
     let header = format!("{},{}\n", "Size", names.join(","));
     if let Some(fname) = APP_FLAGS.value_of("file") {
-        match File::create(&Path::new(fname)) {
+        match File::create(Path::new(fname)) {
             Ok(mut f) => {
-                f.write(header.as_bytes()).expect("File write failed");
-                for (size, times) in results {
-                    f.write(format!("{},{}\n", size, times.join(",")).as_bytes())
+                f.write_all(header.as_bytes()).expect("File write failed");
+                for (size, times) in &results {
+                    f.write_all(format!("{},{}\n", size, row(times)).as_bytes())
                         .expect("File write failed");
                 }
                 return;
             }
             Err(why) => {
-                eprintln!("ERROR: Could not open {}: {}", fname, why.description());
+                eprintln!("ERROR: Could not open {}: {}", fname, why);
                 eprintln!("Displaying results below:");
             }
         }
     }
 
     print!("{}", header);
-    for (size, times) in results {
-        println!("{},{}", size, times.join(","));
+    for (size, times) in &results {
+        println!("{},{}", size, row(times));
     }
 }
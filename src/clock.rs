@@ -1,27 +1,193 @@
 use libc;
+use std::cmp;
+
+/// The number of times each overhead is measured when calibrating a clock
+const CALIBRATION_ROUNDS: usize = 1000;
+
+lazy_static! {
+    static ref CALIBRATIONS: Vec<Calibration> = vec![
+        Calibration::measure(ClockSource::Monotonic),
+        Calibration::measure(ClockSource::ProcessCpu),
+        Calibration::measure(ClockSource::ThreadCpu),
+    ];
+}
+
+/// The clock that decides when a benchmark has run for long enough
+///
+/// Every run is always timed with both a wall clock (`CLOCK_MONOTONIC`) and a CPU clock. This
+/// selects which of the two is compared against `--min_duration`, and which one the spread
+/// statistics (min, max, median, etc.) are reported for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Wall clock time. Includes time spent blocked on I/O or locks.
+    Monotonic,
+    /// CPU time of the whole process, summed over all of its threads
+    ProcessCpu,
+    /// CPU time of the thread running the benchmark
+    ThreadCpu,
+}
+
+impl ClockSource {
+    /// Parses the value passed to `--clock`
+    pub fn from_name(name: &str) -> Option<ClockSource> {
+        match name {
+            "monotonic" => Some(ClockSource::Monotonic),
+            "process_cpu" => Some(ClockSource::ProcessCpu),
+            "thread_cpu" => Some(ClockSource::ThreadCpu),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of this clock source as accepted by `--clock`
+    pub fn name(self) -> &'static str {
+        match self {
+            ClockSource::Monotonic => "monotonic",
+            ClockSource::ProcessCpu => "process_cpu",
+            ClockSource::ThreadCpu => "thread_cpu",
+        }
+    }
+
+    /// Returns the CPU clock that is read alongside the wall clock
+    fn cpu_clock_id(self) -> libc::clockid_t {
+        match self {
+            ClockSource::ThreadCpu => libc::CLOCK_THREAD_CPUTIME_ID,
+            _ => libc::CLOCK_PROCESS_CPUTIME_ID,
+        }
+    }
+}
+
+/// The time measured by a `Clock`, in nanoseconds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Elapsed {
+    /// Wall clock time
+    pub real: u64,
+    /// CPU time (of the process, or of the thread for `ClockSource::ThreadCpu`)
+    pub cpu: u64,
+}
+
+impl Elapsed {
+    /// Returns the time measured by the clock selected with `source`
+    pub fn get(&self, source: ClockSource) -> u64 {
+        match source {
+            ClockSource::Monotonic => self.real,
+            _ => self.cpu,
+        }
+    }
+
+    pub(crate) fn saturating_sub(self, other: Elapsed) -> Elapsed {
+        Elapsed {
+            real: self.real.saturating_sub(other.real),
+            cpu: self.cpu.saturating_sub(other.cpu),
+        }
+    }
+
+    pub(crate) fn scale(self, n: u64) -> Elapsed {
+        Elapsed {
+            real: self.real * n,
+            cpu: self.cpu * n,
+        }
+    }
+}
+
+/// The overhead of timing a run, measured once per `ClockSource` before the first benchmark runs
+///
+/// Reading the clocks is not free (reading a CPU clock is a syscall), and that cost ends up in
+/// every sample. It is reported along with the results, and can be subtracted from every sample
+/// with `--subtract_overhead`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// The clock source that was calibrated
+    pub source: ClockSource,
+    /// The coarsest resolution of the clocks read for `source`, in ns
+    pub resolution: u64,
+    /// The time measured for a run that does nothing, i.e. the cost of starting and stopping the
+    /// clocks
+    pub read: Elapsed,
+    /// The additional time measured for every `State::pause`/`State::resume` pair in a run
+    pub pause_resume: Elapsed,
+}
+
+impl Calibration {
+    /// Returns the calibration for `source`, calibrating every clock source on first use
+    pub(crate) fn get(source: ClockSource) -> Calibration {
+        match source {
+            ClockSource::Monotonic => CALIBRATIONS[0],
+            ClockSource::ProcessCpu => CALIBRATIONS[1],
+            ClockSource::ThreadCpu => CALIBRATIONS[2],
+        }
+    }
+
+    /// Returns the overhead measured by a run that paused and resumed `pauses` times
+    pub(crate) fn overhead(&self, pauses: u64) -> Elapsed {
+        let pause_resume = self.pause_resume.scale(pauses);
+        Elapsed {
+            real: self.read.real + pause_resume.real,
+            cpu: self.read.cpu + pause_resume.cpu,
+        }
+    }
+
+    fn measure(source: ClockSource) -> Calibration {
+        let mut empty = Vec::with_capacity(CALIBRATION_ROUNDS);
+        let mut paused = Vec::with_capacity(CALIBRATION_ROUNDS);
+        for _ in 0..CALIBRATION_ROUNDS {
+            empty.push(Clock::with_source(source).stop());
+
+            let mut clock = Clock::with_source(source);
+            clock.pause();
+            clock.resume();
+            paused.push(clock.stop());
+        }
+
+        let read = median(&mut empty);
+        Calibration {
+            source,
+            resolution: Clock::resolution(source),
+            read,
+            pause_resume: median(&mut paused).saturating_sub(read),
+        }
+    }
+}
+
+/// Returns the median real and CPU time (independently of each other) of `samples`
+fn median(samples: &mut [Elapsed]) -> Elapsed {
+    let mid = samples.len() / 2;
+    samples.sort_by_key(|s| s.real);
+    let real = samples[mid].real;
+    samples.sort_by_key(|s| s.cpu);
+    let cpu = samples[mid].cpu;
+    Elapsed { real, cpu }
+}
 
 pub struct Clock {
     is_paused: bool,
-    start_time: u64,   // Start time in ns
-    elapsed_time: u64, // Elapsed time in ns
+    cpu_clock: libc::clockid_t,
+    start_time: Elapsed,   // Start time in ns
+    elapsed_time: Elapsed, // Elapsed time in ns
 }
 
 impl Clock {
     pub fn new() -> Self {
+        Clock::with_source(ClockSource::ProcessCpu)
+    }
+
+    pub fn with_source(source: ClockSource) -> Self {
+        let cpu_clock = source.cpu_clock_id();
         Clock {
             is_paused: false,
-            start_time: Clock::now(),
-            elapsed_time: 0,
+            cpu_clock,
+            start_time: Clock::now(cpu_clock),
+            elapsed_time: Elapsed::default(),
         }
     }
 
     pub fn pause(&mut self) {
-        let now = Clock::now();
+        let now = Clock::now(self.cpu_clock);
         if self.is_paused {
             panic!("Cannot pause an already paused clock");
         }
 
-        self.elapsed_time += now - self.start_time;
+        self.elapsed_time.real += now.real - self.start_time.real;
+        self.elapsed_time.cpu += now.cpu - self.start_time.cpu;
         self.is_paused = true;
     }
 
@@ -31,29 +197,129 @@ impl Clock {
         }
 
         self.is_paused = false;
-        self.start_time = Clock::now();
+        self.start_time = Clock::now(self.cpu_clock);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.is_paused
     }
 
-    pub fn stop(self) -> u64 {
-        let now = Clock::now();
+    pub fn stop(self) -> Elapsed {
+        let now = Clock::now(self.cpu_clock);
         if self.is_paused {
             panic!("Cannot stop a paused clock");
         }
 
-        self.elapsed_time + now - self.start_time
+        Elapsed {
+            real: self.elapsed_time.real + now.real - self.start_time.real,
+            cpu: self.elapsed_time.cpu + now.cpu - self.start_time.cpu,
+        }
+    }
+
+    /// Returns the coarsest resolution (in ns) of the clocks read for `source`
+    pub(crate) fn resolution(source: ClockSource) -> u64 {
+        let resolution = |clock| {
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+
+            unsafe {
+                if libc::clock_getres(clock, &mut ts) == -1 {
+                    panic!("Error getting clock resolution");
+                }
+            }
+            (ts.tv_sec * 1_000_000_000 + ts.tv_nsec) as u64
+        };
+
+        cmp::max(
+            resolution(libc::CLOCK_MONOTONIC),
+            resolution(source.cpu_clock_id()),
+        )
+    }
+
+    /// Returns the current time of the wall clock (`CLOCK_MONOTONIC`) in ns
+    ///
+    /// Unlike a `Clock`, this can be compared across threads.
+    pub(crate) fn monotonic() -> u64 {
+        Clock::read(libc::CLOCK_MONOTONIC)
     }
 
-    fn now() -> u64 {
+    fn now(cpu_clock: libc::clockid_t) -> Elapsed {
+        Elapsed {
+            real: Clock::read(libc::CLOCK_MONOTONIC),
+            cpu: Clock::read(cpu_clock),
+        }
+    }
+
+    fn read(clock: libc::clockid_t) -> u64 {
         let mut ts = libc::timespec {
             tv_sec: 0,
             tv_nsec: 0,
         };
 
         unsafe {
-            if libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) == -1 {
+            if libc::clock_gettime(clock, &mut ts) == -1 {
                 panic!("Error getting timespec");
             }
         }
         (ts.tv_sec * 1_000_000_000 + ts.tv_nsec) as u64
     }
 }
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calibration, ClockSource, Elapsed};
+
+    fn calibration() -> Calibration {
+        Calibration {
+            source: ClockSource::Monotonic,
+            resolution: 1,
+            read: Elapsed { real: 20, cpu: 30 },
+            pause_resume: Elapsed { real: 40, cpu: 50 },
+        }
+    }
+
+    #[test]
+    fn overhead_scales_pause_resume() {
+        let calibration = calibration();
+        assert_eq!(calibration.overhead(0), calibration.read);
+        assert_eq!(calibration.overhead(1), Elapsed { real: 60, cpu: 80 });
+        assert_eq!(
+            calibration.overhead(3),
+            Elapsed {
+                real: 140,
+                cpu: 180
+            }
+        );
+    }
+
+    #[test]
+    fn subtracted_overhead_saturates() {
+        let overhead = calibration().overhead(2);
+        let elapsed = Elapsed { real: 50, cpu: 500 };
+        assert_eq!(
+            elapsed.saturating_sub(overhead),
+            Elapsed { real: 0, cpu: 370 }
+        );
+    }
+
+    #[test]
+    fn every_source_is_calibrated() {
+        for &source in &[
+            ClockSource::Monotonic,
+            ClockSource::ProcessCpu,
+            ClockSource::ThreadCpu,
+        ] {
+            let calibration = Calibration::get(source);
+            assert_eq!(calibration.source, source);
+            assert!(calibration.resolution > 0);
+        }
+    }
+}
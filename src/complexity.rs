@@ -0,0 +1,132 @@
+use clock::ClockSource;
+
+/// The asymptotic complexity that the times of a benchmark are fitted to
+///
+/// See `Benchmark::with_complexity`. `n` is the (first) argument (i.e. `i` from the range) the
+/// bench function was run with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Complexity {
+    O1,
+    OLogN,
+    ON,
+    ONLogN,
+    ON2,
+    ON3,
+    /// Fit every other complexity and pick the one with the lowest RMS error
+    Auto,
+}
+
+impl Complexity {
+    /// The complexities that `Auto` picks from
+    const CANDIDATES: [Complexity; 6] = [
+        Complexity::O1,
+        Complexity::OLogN,
+        Complexity::ON,
+        Complexity::ONLogN,
+        Complexity::ON2,
+        Complexity::ON3,
+    ];
+
+    /// Returns the name of this complexity as it is reported, e.g. `N^2`
+    pub fn name(self) -> &'static str {
+        match self {
+            Complexity::O1 => "(1)",
+            Complexity::OLogN => "lgN",
+            Complexity::ON => "N",
+            Complexity::ONLogN => "NlgN",
+            Complexity::ON2 => "N^2",
+            Complexity::ON3 => "N^3",
+            Complexity::Auto => "Auto",
+        }
+    }
+
+    fn eval(self, n: f64) -> f64 {
+        match self {
+            Complexity::O1 => 1.0,
+            Complexity::OLogN => n.log2(),
+            Complexity::ON => n,
+            Complexity::ONLogN => n * n.log2(),
+            Complexity::ON2 => n * n,
+            Complexity::ON3 => n * n * n,
+            Complexity::Auto => panic!("Cannot evaluate Complexity::Auto"),
+        }
+    }
+}
+
+/// The result of fitting the times of a benchmark to a `Complexity` with least squares
+///
+/// The fitted model is `time = coefficient * f(n)`. The RMS error is normalized by the mean time,
+/// so `0.05` means that the model is off by 5% on average.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fit {
+    /// The complexity that was fitted. This is never `Complexity::Auto`.
+    pub complexity: Complexity,
+    pub real_coefficient: f64,
+    pub cpu_coefficient: f64,
+    pub real_rms: f64,
+    pub cpu_rms: f64,
+}
+
+impl Fit {
+    /// Fits the mean wall clock and CPU times measured for every argument in `ns`
+    ///
+    /// If `complexity` is `Auto`, the complexity that fits the times of `clock` best is used for
+    /// both (or O(1) if none of them can be fitted).
+    pub(crate) fn new(
+        complexity: Complexity,
+        clock: ClockSource,
+        ns: &[u64],
+        real_times: &[f64],
+        cpu_times: &[f64],
+    ) -> Self {
+        let times = match clock {
+            ClockSource::Monotonic => real_times,
+            _ => cpu_times,
+        };
+        let complexity = match complexity {
+            Complexity::Auto => Complexity::CANDIDATES
+                .iter()
+                .map(|c| (*c, least_squares(*c, ns, times).1))
+                .filter(|(_, rms)| rms.is_finite())
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(Complexity::O1, |(c, _)| c),
+            c => c,
+        };
+
+        let (real_coefficient, real_rms) = least_squares(complexity, ns, real_times);
+        let (cpu_coefficient, cpu_rms) = least_squares(complexity, ns, cpu_times);
+        Fit {
+            complexity,
+            real_coefficient,
+            cpu_coefficient,
+            real_rms,
+            cpu_rms,
+        }
+    }
+}
+
+/// Returns the coefficient that minimizes the squared error of `complexity` for `times`, along
+/// with the normalized RMS error of that fit
+///
+/// Arguments for which `complexity` is undefined (i.e. 0 for `lgN` and `NlgN`) are left out of
+/// the fit.
+fn least_squares(complexity: Complexity, ns: &[u64], times: &[f64]) -> (f64, f64) {
+    let points: Vec<(f64, f64)> = ns
+        .iter()
+        .map(|n| complexity.eval(*n as f64))
+        .zip(times.iter().cloned())
+        .filter(|(f, _)| f.is_finite())
+        .collect();
+    let coefficient = points.iter().map(|(f, t)| f * t).sum::<f64>()
+        / points.iter().map(|(f, _)| f * f).sum::<f64>();
+
+    let count = points.len() as f64;
+    let mean = points.iter().map(|(_, t)| t).sum::<f64>() / count;
+    let rms = (points
+        .iter()
+        .map(|(f, t)| (t - coefficient * f).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+    (coefficient, rms / mean)
+}
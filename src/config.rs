@@ -1,15 +1,75 @@
 use clap::{App, Arg};
-use regex::Regex;
+use clock::ClockSource;
+use regex::{self, Regex};
+use reporter::Format;
 use std::cmp;
-use std::error::Error;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 const DEFAULT_MIN_DURATION: &str = "1";
 const DEFAULT_MIN_RUNS: &str = "8";
+const DEFAULT_FORMAT: &str = "csv";
+const DEFAULT_CLOCK: &str = "process_cpu";
+const DEFAULT_WARMUP_DURATION: &str = "0";
+const DEFAULT_WARMUP_RUNS: &str = "0";
+const DEFAULT_REPETITIONS: &str = "1";
 
+/// Controls how benchmarks are run and reported
+///
+/// `Benchmark::run` uses the config parsed from the command line of the process (see
+/// `Config::from_args`). To embed pew in a binary with its own command line, or to run benchmarks
+/// from tests, build a config programmatically and pass it to `Benchmark::run_with` instead.
+///
+/// # Examples
+///
+/// ```
+/// use pew::{Config, Format};
+/// use std::time::Duration;
+///
+/// let config = Config::new()
+///     .with_filter("vector")
+///     .with_min_duration(Duration::from_millis(100))
+///     .with_min_runs(16)
+///     .with_warmup_runs(4)
+///     .with_format(Format::Json);
+/// ```
+#[derive(Clone, Debug)]
 pub struct Config {
-    pub filter: Regex,
-    pub min_duration: u64,
-    pub min_runs: u8,
+    pub(crate) filter: Regex,
+    pub(crate) min_duration: u64,
+    pub(crate) min_runs: u8,
+    pub(crate) format: Format,
+    pub(crate) clock: ClockSource,
+    pub(crate) subtract_overhead: bool,
+    pub(crate) warmup_duration: u64,
+    pub(crate) warmup_runs: u64,
+    pub(crate) verbose: bool,
+    pub(crate) repetitions: u64,
+    pub(crate) report_aggregates_only: bool,
+    pub(crate) save_baseline: Option<String>,
+    pub(crate) baseline: Option<String>,
+    pub(crate) baseline_dir: PathBuf,
+    pub(crate) fail_if_slower: Option<f64>,
+    pub(crate) list: bool,
+}
+
+/// Returns the directory baselines are saved in unless `Config::with_baseline_dir` is used, i.e.
+/// `pew` in the target directory of cargo
+fn default_baseline_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| PathBuf::from("target"), PathBuf::from)
+        .join("pew")
+}
+
+/// Parses the value passed to `--fail-if-slower`, a non-negative percentage such as `5%`
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    let error = || format!("{} is not a percentage, e.g. 5%", percent);
+    let number = percent.strip_suffix('%').ok_or_else(error)?;
+    match number.parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok(number),
+        _ => Err(error()),
+    }
 }
 
 fn create_config() -> Config {
@@ -43,14 +103,147 @@ fn create_config() -> Config {
                 .takes_value(true)
                 .default_value(DEFAULT_MIN_RUNS),
         )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("The output format of the benchmark results")
+                .takes_value(true)
+                .possible_values(&["console", "csv", "json", "google_json", "libtest"])
+                .default_value(DEFAULT_FORMAT),
+        )
+        .arg(
+            Arg::with_name("clock")
+                .long("clock")
+                .value_name("CLOCK")
+                .help("The clock that is compared against --min_duration")
+                .takes_value(true)
+                .possible_values(&["monotonic", "process_cpu", "thread_cpu"])
+                .default_value(DEFAULT_CLOCK),
+        )
+        .arg(
+            Arg::with_name("subtract_overhead")
+                .long("subtract_overhead")
+                .help("Subtract the calibrated overhead of reading the clock from every run"),
+        )
+        .arg(
+            Arg::with_name("warmup_duration")
+                .long("warmup_duration")
+                .value_name("WARMUP_DURATION")
+                .help("Run benchmarks for this long (in s) before measuring them")
+                .takes_value(true)
+                .default_value(DEFAULT_WARMUP_DURATION),
+        )
+        .arg(
+            Arg::with_name("warmup_runs")
+                .long("warmup_runs")
+                .value_name("WARMUP_RUNS")
+                .help("Run benchmarks at least this many times before measuring them")
+                .takes_value(true)
+                .default_value(DEFAULT_WARMUP_RUNS),
+        )
+        .arg(
+            Arg::with_name("repetitions")
+                .long("repetitions")
+                .value_name("REPETITIONS")
+                .help(
+                    "Repeat every benchmark this many times and report aggregates across the \
+                     repetitions",
+                )
+                .takes_value(true)
+                .default_value(DEFAULT_REPETITIONS),
+        )
+        .arg(
+            Arg::with_name("report_aggregates_only")
+                .long("report_aggregates_only")
+                .help("Only report the aggregates of repeated benchmarks, not every repetition"),
+        )
+        .arg(
+            Arg::with_name("save_baseline")
+                .long("save-baseline")
+                .value_name("NAME")
+                .help("Save the results to a baseline with this name")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .value_name("NAME")
+                .help(
+                    "Compare the results to the baseline with this name, or to the file of a \
+                     baseline or of the json output if NAME ends in .json",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fail_if_slower")
+                .long("fail-if-slower")
+                .value_name("PERCENT")
+                .help(
+                    "Exit with an error if a benchmark is significantly slower \
+                     than the baseline by more than this percentage, e.g. 5%",
+                )
+                .takes_value(true)
+                .validator(|percent| parse_percent(&percent).map(|_| ()))
+                .requires("baseline"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Print details about how each benchmark was run to stderr"),
+        )
+        .arg(
+            Arg::with_name("name_filter")
+                .value_name("FILTER")
+                .help("Only run benchmarks with a name that contains this string, like libtest")
+                .conflicts_with("filter")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("exact")
+                .long("exact")
+                .help("Only run the benchmark named exactly FILTER"),
+        )
+        .arg(
+            Arg::with_name("list")
+                .long("list")
+                .help("List the benchmarks instead of running them"),
+        )
+        .arg(
+            Arg::with_name("test")
+                .long("test")
+                .help("Run every benchmark only briefly, to check that it works"),
+        )
+        .arg(
+            Arg::with_name("bench")
+                .long("bench")
+                .help("Accepted for compatibility with libtest (passed by cargo bench)"),
+        )
+        .arg(
+            Arg::with_name("nocapture")
+                .long("nocapture")
+                .help("Accepted for compatibility with libtest"),
+        )
         .get_matches();
 
-    let filter = match app_config.value_of("filter") {
+    let name_filter = app_config.value_of("name_filter").map(|name| {
+        let name = regex::escape(name);
+        if app_config.is_present("exact") {
+            format!("^{}$", name)
+        } else {
+            name
+        }
+    });
+    let filter = name_filter
+        .as_deref()
+        .or_else(|| app_config.value_of("filter"));
+    let filter = match filter {
         None => Regex::new("").expect("Empty string should be a valid regex"),
         Some(s) => match Regex::new(s) {
             Ok(f) => f,
             Err(e) => {
-                eprintln!("Illegal regex {}: {}", s, e.description());
+                eprintln!("Illegal regex {}: {}", s, e);
                 Regex::new("").expect("Empty string should be a valid regex")
             }
         },
@@ -69,11 +262,60 @@ fn create_config() -> Config {
             .unwrap(),
         2,
     );
-    Config {
+    let format = Format::from_name(app_config.value_of("format").unwrap())
+        .expect("clap should only allow valid formats");
+    let clock = ClockSource::from_name(app_config.value_of("clock").unwrap())
+        .expect("clap should only allow valid clocks");
+    let subtract_overhead = app_config.is_present("subtract_overhead");
+    let warmup_duration = (app_config
+        .value_of("warmup_duration")
+        .unwrap()
+        .parse::<f64>()
+        .unwrap()
+        * 1e9) as u64;
+    let warmup_runs = app_config
+        .value_of("warmup_runs")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let verbose = app_config.is_present("verbose");
+    let repetitions = cmp::max(
+        app_config
+            .value_of("repetitions")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap(),
+        1,
+    );
+    let report_aggregates_only = app_config.is_present("report_aggregates_only");
+    let save_baseline = app_config.value_of("save_baseline").map(String::from);
+    let baseline = app_config.value_of("baseline").map(String::from);
+    let fail_if_slower = app_config
+        .value_of("fail_if_slower")
+        .map(|percent| parse_percent(percent).expect("Validated by clap"));
+    let list = app_config.is_present("list");
+    let config = Config {
         filter,
         min_duration,
         min_runs,
+        format,
+        clock,
+        subtract_overhead,
+        warmup_duration,
+        warmup_runs,
+        verbose,
+        repetitions,
+        report_aggregates_only,
+        save_baseline,
+        baseline,
+        baseline_dir: default_baseline_dir(),
+        fail_if_slower,
+        list,
+    };
+    if app_config.is_present("test") {
+        return config.for_test();
     }
+    config
 }
 
 lazy_static! {
@@ -81,7 +323,284 @@ lazy_static! {
 }
 
 impl Config {
-    pub fn get() -> &'static Config {
-        return &PEW_CONFIG;
+    /// Returns a config with the same defaults as the command line flags
+    ///
+    /// Unlike `Config::from_args`, this does not look at the arguments of the process.
+    pub fn new() -> Self {
+        Config {
+            filter: Regex::new("").expect("Empty string should be a valid regex"),
+            min_duration: 1_000_000_000,
+            min_runs: 8,
+            format: Format::Csv,
+            clock: ClockSource::ProcessCpu,
+            subtract_overhead: false,
+            warmup_duration: 0,
+            warmup_runs: 0,
+            verbose: false,
+            repetitions: 1,
+            report_aggregates_only: false,
+            save_baseline: None,
+            baseline: None,
+            baseline_dir: default_baseline_dir(),
+            fail_if_slower: None,
+            list: false,
+        }
+    }
+
+    /// Parses the config from the command line arguments of the process
+    ///
+    /// Run the binary with `-h` to see the available flags. Note that this exits the process on
+    /// unknown flags or `--help`, so it should only be used by binaries whose command line is
+    /// entirely owned by pew.
+    pub fn from_args() -> Self {
+        create_config()
+    }
+
+    /// Only run benchmarks with a name that matches this regex
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filter` is not a valid regex.
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = match Regex::new(filter) {
+            Ok(f) => f,
+            Err(e) => panic!("Illegal regex {}: {}", filter, e),
+        };
+        self
+    }
+
+    /// Run each benchmark until at least this much time has been measured
+    pub fn with_min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = duration_ns(min_duration);
+        self
+    }
+
+    /// Run each benchmark at least this many times. This will always be at least 2.
+    pub fn with_min_runs(mut self, min_runs: u8) -> Self {
+        self.min_runs = cmp::max(min_runs, 2);
+        self
+    }
+
+    /// Sets the format used to report results if the benchmark has no reporter of its own
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the clock that decides when a benchmark has run for long enough
+    ///
+    /// This is used by every benchmark that does not set its own with `Benchmark::with_clock`.
+    pub fn with_clock(mut self, clock: ClockSource) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Subtract the calibrated overhead of reading the clock (see `Calibration`) from every run
+    ///
+    /// Runs that took less than the overhead are reported as taking no time at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// let config = Config::new()
+    ///     .with_min_duration(Duration::from_millis(10))
+    ///     .with_subtract_overhead(true);
+    /// let results = Benchmark::with_name("empty")
+    ///     .with_range(1, 1, 2)
+    ///     .with_bench(("bm_nothing", |_: &mut pew::State<u64>| {}))
+    ///     .run_with(&config);
+    ///
+    /// // Runs that were faster than the calibrated overhead do not wrap around to huge times
+    /// assert!(results[0].cpu_samples.iter().all(|&sample| sample < 1e6));
+    /// ```
+    pub fn with_subtract_overhead(mut self, subtract_overhead: bool) -> Self {
+        self.subtract_overhead = subtract_overhead;
+        self
+    }
+
+    /// Run each benchmark for at least this long before measuring it
+    ///
+    /// The warmup runs are executed exactly like measured runs, but their times are discarded.
+    /// This is used by every benchmark that does not set its own with
+    /// `Benchmark::with_warmup_duration`.
+    pub fn with_warmup_duration(mut self, warmup_duration: Duration) -> Self {
+        self.warmup_duration = duration_ns(warmup_duration);
+        self
+    }
+
+    /// Run each benchmark at least this many times before measuring it
+    ///
+    /// This is used by every benchmark that does not set its own with
+    /// `Benchmark::with_warmup_runs`.
+    pub fn with_warmup_runs(mut self, warmup_runs: u64) -> Self {
+        self.warmup_runs = warmup_runs;
+        self
+    }
+
+    /// Repeat every benchmark this many times. This will always be at least 1.
+    ///
+    /// Each repetition runs the bench function until `min_runs` and `min_duration` are satisfied
+    /// again. If there is more than one, the repetitions are followed by their mean, median,
+    /// standard deviation and coefficient of variation (see `Aggregate`). This is used by every
+    /// benchmark that does not set its own with `Benchmark::with_repetitions`.
+    pub fn with_repetitions(mut self, repetitions: u64) -> Self {
+        self.repetitions = cmp::max(repetitions, 1);
+        self
+    }
+
+    /// Only pass the aggregates of repeated benchmarks to the reporter, not every repetition
+    ///
+    /// `Benchmark::run` still returns every repetition.
+    pub fn with_report_aggregates_only(mut self, report_aggregates_only: bool) -> Self {
+        self.report_aggregates_only = report_aggregates_only;
+        self
+    }
+
+    /// Save the results of every benchmark to the baseline called `name`
+    ///
+    /// The samples and statistics of every benchmark are saved to `<name>.json` in the baseline
+    /// directory (see `with_baseline_dir`), or to `name` itself if it ends in `.json`. Benchmarks
+    /// that were saved to the same baseline before are replaced.
+    pub fn with_save_baseline(mut self, name: &str) -> Self {
+        self.save_baseline = Some(name.to_string());
+        self
+    }
+
+    /// Compare the results of every benchmark to the baseline called `name`
+    ///
+    /// If `name` ends in `.json`, it is the path of a file to compare to instead. This can be a
+    /// saved baseline or the output of the `JsonReporter`.
+    ///
+    /// Every benchmark that is in the baseline gets a `Comparison` with the percent change of its
+    /// mean time and whether the change is statistically significant, which is reported next to
+    /// its other results.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let dir = std::env::temp_dir().join("pew_baseline_example");
+    ///     let config = Config::new()
+    ///         .with_min_duration(Duration::from_millis(10))
+    ///         .with_baseline_dir(&dir);
+    ///     let run = |config: &Config| {
+    ///         Benchmark::with_name("sum")
+    ///             .with_range(64, 64, 2)
+    ///             .with_bench(pew_bench!(bm_sum))
+    ///             .run_with(config)
+    ///     };
+    ///
+    ///     run(&config.clone().with_save_baseline("before"));
+    ///     let results = run(&config.with_baseline("before"));
+    ///
+    ///     let comparison = results[0].comparison.as_ref().unwrap();
+    ///     assert_eq!(comparison.baseline, "before");
+    ///     assert!(comparison.p_value >= 0.0 && comparison.p_value <= 1.0);
+    /// }
+    /// ```
+    pub fn with_baseline(mut self, name: &str) -> Self {
+        self.baseline = Some(name.to_string());
+        self
+    }
+
+    /// Exit the process with an error if a benchmark is significantly slower than the baseline
+    /// (see `with_baseline`) by more than `percent`
+    ///
+    /// Every benchmark that regressed is recorded, and once every suite has run they are printed
+    /// to stderr with the confidence interval of their change, and the process exits with status
+    /// 1 if there are any. This happens when the process exits for programs that use
+    /// `Benchmark::run`, and in `pew::finish` for programs that use `Benchmark::run_with`, which
+    /// never exits. This is meant to fail CI jobs on performance regressions. The results can
+    /// also be checked directly with `Comparison::is_regression`.
+    ///
+    /// A baseline must be set (see `with_baseline`) and readable, as comparing to nothing would
+    /// hide every regression: running a benchmark panics otherwise.
+    pub fn with_fail_if_slower(mut self, percent: f64) -> Self {
+        self.fail_if_slower = Some(percent);
+        self
+    }
+
+    /// Only print the name of every benchmark that would run (like libtest's `--list`) instead of
+    /// running it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config};
+    ///
+    /// fn bm_sum(state: &mut pew::State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     // Prints `sum/bm_sum/16: bench` and `sum/bm_sum/32: bench`
+    ///     let results = Benchmark::with_name("sum")
+    ///         .with_range(16, 32, 2)
+    ///         .with_bench(pew_bench!(bm_sum))
+    ///         .run_with(&Config::new().with_list(true));
+    ///
+    ///     assert!(results.is_empty());
+    /// }
+    /// ```
+    pub fn with_list(mut self, list: bool) -> Self {
+        self.list = list;
+        self
+    }
+
+    /// Run every benchmark just long enough to check that it works, like libtest's `--test`
+    ///
+    /// This sets the fewest runs possible without warmup or repetitions.
+    fn for_test(self) -> Self {
+        self.with_min_duration(Duration::from_secs(0))
+            .with_min_runs(2)
+            .with_warmup_duration(Duration::from_secs(0))
+            .with_warmup_runs(0)
+            .with_repetitions(1)
+    }
+
+    /// Sets the directory baselines are saved in. Defaults to `pew` in the target directory of
+    /// cargo (i.e. `target/pew` unless `CARGO_TARGET_DIR` is set).
+    pub fn with_baseline_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.baseline_dir = dir.into();
+        self
+    }
+
+    /// Print details about how each benchmark was run (such as the number of warmup runs) to
+    /// stderr
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Returns the config parsed from the command line, parsing it on first use
+    pub(crate) fn get() -> &'static Config {
+        &PEW_CONFIG
+    }
+}
+
+/// Converts `duration` to nanoseconds
+pub(crate) fn duration_ns(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
     }
 }
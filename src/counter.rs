@@ -0,0 +1,163 @@
+use std::ops::{BitOr, BitOrAssign};
+
+/// Controls how the values a bench function gives a `Counter` are combined into a result
+///
+/// Flags can be combined with `|`. They are applied in the order they are declared, so
+/// `AVG_ITERATIONS | INVERT` reports the number of iterations per unit of the counter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CounterFlags(u8);
+
+impl CounterFlags {
+    /// Report the sum of the values of every run. This is the default.
+    pub const SUM: CounterFlags = CounterFlags(0);
+    /// Divide the sum by the number of runs
+    pub const AVG_RUNS: CounterFlags = CounterFlags(1);
+    /// Divide the sum by the number of iterations of every run (see `State::iter`)
+    pub const AVG_ITERATIONS: CounterFlags = CounterFlags(1 << 1);
+    /// Divide the sum by the total time (in seconds) of every run
+    pub const RATE: CounterFlags = CounterFlags(1 << 2);
+    /// Report the inverse of the value, e.g. seconds per item rather than items per second
+    pub const INVERT: CounterFlags = CounterFlags(1 << 3);
+
+    /// Returns whether all of the flags in `other` are set
+    pub fn contains(self, other: CounterFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for CounterFlags {
+    type Output = CounterFlags;
+
+    fn bitor(self, other: CounterFlags) -> CounterFlags {
+        CounterFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for CounterFlags {
+    fn bitor_assign(&mut self, other: CounterFlags) {
+        self.0 |= other.0;
+    }
+}
+
+/// A user defined metric reported alongside the time of a benchmark (see `State::counter`)
+///
+/// # Examples
+///
+/// ```
+/// use pew::{Counter, CounterFlags, State};
+///
+/// fn bm_lookup(state: &mut State<u64>) {
+///     let n = state.get_input();
+///     let mut hits = 0;
+///     for i in state.iter() {
+///         if pew::do_not_optimize(i % 3) == 0 {
+///             hits += 1;
+///         }
+///     }
+///     state.counter("hits", hits);
+///     state.counter("hit_rate", Counter::new(hits as f64, CounterFlags::RATE));
+///     state.counter("size", Counter::new(n as f64, CounterFlags::AVG_RUNS));
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Counter {
+    pub value: f64,
+    pub flags: CounterFlags,
+}
+
+impl Counter {
+    pub fn new(value: f64, flags: CounterFlags) -> Self {
+        Counter { value, flags }
+    }
+
+    /// Combines the sum of the values of every run according to the flags of this counter
+    ///
+    /// Returns `None` for a rate if the runs took no time at all, e.g. once the overhead of the
+    /// clock was subtracted (see `Config::with_subtract_overhead`).
+    pub(crate) fn finish(self, runs: u64, iterations: u64, seconds: f64) -> Option<Counter> {
+        if self.flags.contains(CounterFlags::RATE) && seconds == 0.0 {
+            return None;
+        }
+        let mut value = self.value;
+        if self.flags.contains(CounterFlags::AVG_RUNS) {
+            value /= runs as f64;
+        }
+        if self.flags.contains(CounterFlags::AVG_ITERATIONS) {
+            value /= (runs * iterations) as f64;
+        }
+        if self.flags.contains(CounterFlags::RATE) {
+            value /= seconds;
+        }
+        if self.flags.contains(CounterFlags::INVERT) {
+            value = 1.0 / value;
+        }
+        Some(Counter::new(value, self.flags))
+    }
+}
+
+impl From<f64> for Counter {
+    fn from(value: f64) -> Self {
+        Counter::new(value, CounterFlags::SUM)
+    }
+}
+
+impl From<u64> for Counter {
+    fn from(value: u64) -> Self {
+        Counter::new(value as f64, CounterFlags::SUM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counter, CounterFlags};
+
+    #[test]
+    fn finish_applies_every_flag() {
+        // A sum of 24 over 3 runs of 4 iterations that took 2 seconds
+        let cases = [
+            (CounterFlags::SUM, 24.0),
+            (CounterFlags::AVG_RUNS, 8.0),
+            (CounterFlags::AVG_ITERATIONS, 2.0),
+            (CounterFlags::RATE, 12.0),
+            (CounterFlags::INVERT, 1.0 / 24.0),
+            (CounterFlags::AVG_RUNS | CounterFlags::RATE, 4.0),
+            (
+                CounterFlags::AVG_RUNS | CounterFlags::AVG_ITERATIONS,
+                2.0 / 3.0,
+            ),
+            (CounterFlags::AVG_ITERATIONS | CounterFlags::INVERT, 0.5),
+            (CounterFlags::RATE | CounterFlags::INVERT, 1.0 / 12.0),
+            (
+                CounterFlags::AVG_ITERATIONS | CounterFlags::RATE | CounterFlags::INVERT,
+                1.0,
+            ),
+        ];
+        for &(flags, expected) in &cases {
+            let counter = Counter::new(24.0, flags).finish(3, 4, 2.0).unwrap();
+            assert!(
+                (counter.value - expected).abs() < 1e-12,
+                "{:?} gave {} instead of {}",
+                flags,
+                counter.value,
+                expected
+            );
+            assert_eq!(counter.flags, flags);
+        }
+    }
+
+    #[test]
+    fn rates_of_runs_that_took_no_time_are_left_out() {
+        assert_eq!(
+            Counter::new(0.0, CounterFlags::RATE).finish(3, 4, 0.0),
+            None
+        );
+        assert_eq!(
+            Counter::new(0.0, CounterFlags::RATE | CounterFlags::INVERT).finish(3, 4, 0.0),
+            None
+        );
+        assert_eq!(
+            Counter::new(24.0, CounterFlags::AVG_RUNS).finish(3, 4, 0.0),
+            Some(Counter::new(8.0, CounterFlags::AVG_RUNS))
+        );
+    }
+}
@@ -0,0 +1,119 @@
+/// An input that is set up once per argument and torn down when the argument is done
+///
+/// This is an alternative to a generator (see `Benchmark::with_fixture`) for inputs that own
+/// resources, like temporary files, threads or sockets. `setup` is called with the argument
+/// (or the output of the last generator) before the first run, `reset` before every run, and
+/// `teardown` once every bench function has finished with the argument. Teardown happens even if
+/// a bench function panics.
+///
+/// Every run borrows the same fixture, so bench functions should access it with `State::input`
+/// or `State::input_mut`, and `State::get_input` panics. Fixtures do not need to implement
+/// `Clone`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate pew;
+/// use pew::{Benchmark, Config, Fixture, State};
+/// use std::fs::{self, File};
+/// use std::io::{Read, Write};
+/// use std::path::PathBuf;
+/// use std::time::Duration;
+///
+/// struct TempFile {
+///     path: PathBuf,
+/// }
+///
+/// impl Fixture for TempFile {
+///     fn setup(size: u64) -> Self {
+///         let path = std::env::temp_dir().join(format!("pew_fixture_{}", size));
+///         File::create(&path).unwrap().write_all(&vec![0; size as usize]).unwrap();
+///         TempFile { path }
+///     }
+///
+///     fn teardown(self) {
+///         fs::remove_file(&self.path).unwrap();
+///     }
+/// }
+///
+/// fn bm_read(state: &mut State<TempFile>) {
+///     let mut contents = Vec::new();
+///     File::open(&state.input().path).unwrap().read_to_end(&mut contents).unwrap();
+///     pew::do_not_optimize(contents);
+/// }
+///
+/// fn main() {
+///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+///     let results = Benchmark::with_name("file")
+///         .with_range(1 << 12, 1 << 12, 2)
+///         .with_fixture::<TempFile>()
+///         .with_bench(pew_bench!(bm_read))
+///         .run_with(&config);
+///
+///     assert_eq!(results[0].name(), "file/bm_read/4096");
+///     assert!(!std::env::temp_dir().join("pew_fixture_4096").exists());
+/// }
+/// ```
+///
+/// Each argument gets a fixture of its own, which is torn down before the next one is set up and
+/// reset before every run:
+///
+/// ```
+/// #[macro_use]
+/// extern crate pew;
+/// use pew::{Benchmark, Config, Fixture, State};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+///
+/// static SETUPS: AtomicU64 = AtomicU64::new(0);
+/// static RESETS: AtomicU64 = AtomicU64::new(0);
+/// static TEARDOWNS: AtomicU64 = AtomicU64::new(0);
+///
+/// struct Counted(u64);
+///
+/// impl Fixture for Counted {
+///     fn setup(arg: u64) -> Self {
+///         assert_eq!(SETUPS.fetch_add(1, Ordering::SeqCst), TEARDOWNS.load(Ordering::SeqCst));
+///         Counted(arg)
+///     }
+///
+///     fn reset(&mut self) {
+///         RESETS.fetch_add(1, Ordering::SeqCst);
+///     }
+///
+///     fn teardown(self) {
+///         TEARDOWNS.fetch_add(1, Ordering::SeqCst);
+///     }
+/// }
+///
+/// fn bm_read(state: &mut State<Counted>) {
+///     assert!(state.input().0 == 1 || state.input().0 == 2);
+/// }
+///
+/// fn main() {
+///     let config = Config::new().with_min_duration(Duration::from_secs(0));
+///     let results = Benchmark::with_name("counted")
+///         .with_range(1, 2, 2)
+///         .with_fixture::<Counted>()
+///         .with_bench(pew_bench!(bm_read))
+///         .with_bench(("bm_read_again", bm_read))
+///         .run_with(&config);
+///
+///     assert_eq!(SETUPS.load(Ordering::SeqCst), 2);
+///     assert_eq!(TEARDOWNS.load(Ordering::SeqCst), 2);
+///     let runs: u64 = results.iter().map(|result| result.runs).sum();
+///     assert_eq!(RESETS.load(Ordering::SeqCst), runs);
+/// }
+/// ```
+pub trait Fixture<A = u64>: Sized {
+    /// Creates the fixture for an argument
+    fn setup(arg: A) -> Self;
+
+    /// Called before every run of every bench function, e.g. to restore state that a previous run
+    /// modified. Does nothing by default.
+    fn reset(&mut self) {}
+
+    /// Releases the resources of the fixture. Drops it by default.
+    fn teardown(self) {}
+}
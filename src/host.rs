@@ -0,0 +1,122 @@
+use libc;
+use std::fs;
+use std::mem;
+use std::ptr;
+
+/// A CPU cache of the machine the benchmarks run on
+pub(crate) struct Cache {
+    /// `Data`, `Instruction` or `Unified`
+    pub(crate) kind: String,
+    pub(crate) level: u64,
+    /// The size in bytes
+    pub(crate) size: u64,
+    /// The number of CPUs sharing the cache
+    pub(crate) num_sharing: u64,
+}
+
+/// Returns the current UTC time in ISO 8601 format, e.g. `2018-06-01T12:00:00+00:00`
+pub(crate) fn date() -> String {
+    unsafe {
+        let now = libc::time(ptr::null_mut());
+        let mut tm: libc::tm = mem::zeroed();
+        libc::gmtime_r(&now, &mut tm);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00:00",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+/// Returns the name of the machine, or an empty string if it cannot be read
+pub(crate) fn host_name() -> String {
+    let mut name = [0u8; 256];
+    let ok = unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } == 0;
+    if !ok {
+        return String::new();
+    }
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+/// Returns the number of online CPUs
+pub(crate) fn num_cpus() -> u64 {
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    cpus.max(0) as u64
+}
+
+/// Returns the clock rate of the first CPU in MHz, or 0 if it is unknown
+pub(crate) fn mhz_per_cpu() -> f64 {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| {
+            cpuinfo
+                .lines()
+                .find(|line| line.starts_with("cpu MHz"))
+                .and_then(|line| line.split(':').nth(1))
+                .and_then(|mhz| mhz.trim().parse().ok())
+        })
+        .unwrap_or(0.0)
+}
+
+/// Returns whether the CPU frequency may change while benchmarking, i.e. whether the frequency
+/// governor is not `performance`
+pub(crate) fn cpu_scaling_enabled() -> bool {
+    fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor")
+        .map(|governor| governor.trim() != "performance")
+        .unwrap_or(false)
+}
+
+/// Returns the caches of the first CPU, or nothing if they are unknown
+pub(crate) fn caches() -> Vec<Cache> {
+    let mut caches = Vec::new();
+    for index in 0.. {
+        let dir = format!("/sys/devices/system/cpu/cpu0/cache/index{}", index);
+        let read = |file: &str| fs::read_to_string(format!("{}/{}", dir, file)).ok();
+        let (kind, level, size, shared) = match (
+            read("type"),
+            read("level"),
+            read("size"),
+            read("shared_cpu_map"),
+        ) {
+            (Some(kind), Some(level), Some(size), Some(shared)) => (kind, level, size, shared),
+            _ => break,
+        };
+
+        let size = size.trim();
+        let (size, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => (&size[..i], &size[i..]),
+            None => (size, ""),
+        };
+        let unit = match unit {
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => 1,
+        };
+        let num_sharing = shared
+            .trim()
+            .split(',')
+            .filter_map(|word| u32::from_str_radix(word, 16).ok())
+            .map(|word| u64::from(word.count_ones()))
+            .sum();
+        caches.push(Cache {
+            kind: kind.trim().to_string(),
+            level: level.trim().parse().unwrap_or(0),
+            size: size.parse::<u64>().unwrap_or(0) * unit,
+            num_sharing,
+        });
+    }
+    caches
+}
+
+/// Returns the system load averaged over the last 1, 5 and 15 minutes
+pub(crate) fn load_avg() -> Vec<f64> {
+    let mut load = [0.0; 3];
+    let samples = unsafe { libc::getloadavg(load.as_mut_ptr(), 3) };
+    load[..samples.max(0) as usize].to_vec()
+}
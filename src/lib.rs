@@ -14,7 +14,6 @@
  * limitations under the License.
  */
 
-#![feature(asm)]
 #![doc(issue_tracker_base_url = "https://github.com/akshaynanavati/pew/issues/")]
 
 //! # Pew is a benchmarking library
@@ -29,7 +28,10 @@
 //!
 //! The benchmark will run for at least 1 second (or the user specified
 //! `--min_duration`) and at least 8 runs (or the user specified `--min_runs`).
-//! The average of these runs is output as the `Time (ns)` column.
+//! Every run is timed with both the wall clock and the CPU clock. The averages of these runs are
+//! output as the `Time (ns)` and `CPU (ns)` columns, followed by the min, max, median, standard
+//! deviation, coefficient of variation, p90 and p99 of the individual runs, as measured by the
+//! clock selected with `--clock`. That clock also decides when `--min_duration` has been reached.
 //!
 //! The following flags are available when running the benchmark binary:
 //!
@@ -38,55 +40,117 @@
 //! A benchmarking library for Rust based on google/benchmark
 //!
 //! USAGE:
-//!     example1 [OPTIONS]
+//!     example1 [FLAGS] [OPTIONS] [FILTER]
 //!
 //! FLAGS:
-//!     -h, --help       Prints help information
-//!     -V, --version    Prints version information
+//!     -h, --help                      Prints help information
+//!         --report_aggregates_only    Only report the aggregates of repeated benchmarks, not every repetition
+//!         --subtract_overhead         Subtract the calibrated overhead of reading the clock from every run
+//!     -V, --version                   Prints version information
+//!     -v, --verbose                   Print details about how each benchmark was run to stderr
+//!         --bench                     Accepted for compatibility with libtest (passed by cargo bench)
+//!         --exact                     Only run the benchmark named exactly FILTER
+//!         --list                      List the benchmarks instead of running them
+//!         --nocapture                 Accepted for compatibility with libtest
+//!         --test                      Run every benchmark only briefly, to check that it works
 //!
 //! OPTIONS:
-//!    -f, --filter <FILTER>             Only run benchmarks with a name that matches this regex
-//!    -d, --min_duration <RUN_UNTIL>    Run benchmarks till this time (in s) and then output average [default: 1]
-//!     -r, --min_runs <MIN_RUNS>        Run benchmarks for at least this many runs [default: 8]
+//!     -f, --filter <FILTER>                      Only run benchmarks with a name that matches this regex
+//!     -d, --min_duration <RUN_UNTIL>             Run benchmarks till this time (in s) and then output average [default: 1]
+//!     -r, --min_runs <MIN_RUNS>                  Run benchmarks for at least this many runs. This will be always be at least 2. [default: 8]
+//!         --format <FORMAT>                      The output format of the benchmark results [default: csv]
+//!                                                [possible values: console, csv, json, google_json, libtest]
+//!         --clock <CLOCK>                        The clock that is compared against --min_duration
+//!                                                [default: process_cpu]
+//!                                                [possible values: monotonic, process_cpu, thread_cpu]
+//!         --repetitions <REPETITIONS>            Repeat every benchmark this many times and report aggregates across the repetitions [default: 1]
+//!         --warmup_duration <WARMUP_DURATION>    Run benchmarks for this long (in s) before measuring them [default: 0]
+//!         --warmup_runs <WARMUP_RUNS>            Run benchmarks at least this many times before measuring them [default: 0]
+//!         --save-baseline <NAME>                 Save the results to a baseline with this name
+//!         --baseline <NAME>                      Compare the results to the baseline with this name, or to the file of a baseline or of the json output if NAME ends in .json
+//!         --fail-if-slower <PERCENT>             Exit with an error if a benchmark is significantly slower than the baseline by more than this percentage, e.g. 5%
+//!
+//! ARGS:
+//!     <FILTER>    Only run benchmarks with a name that contains this string, like libtest
 //!  ```
 //!
 //!  Use `-h` to get the most up to date flags.
+//!
+//!  These flags are only parsed by `Benchmark::run`. Binaries with their own command line (and
+//!  tests) should build a `Config` and use `Benchmark::run_with` instead.
+//!
+//!  Results are handed to a `Reporter`. `--format` picks one of the built in reporters; a custom
+//!  one can be set with `Benchmark::with_reporter`.
 
 extern crate clap;
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
 extern crate regex;
+#[macro_use]
+extern crate serde_json;
 
 mod clock;
 mod benchmark;
 mod state;
 mod config;
+mod stats;
+mod reporter;
+mod result;
+mod complexity;
+mod counter;
+mod arg_spec;
+mod fixture;
+mod baseline;
+mod host;
 
-pub use benchmark::Benchmark;
-pub use state::State;
-pub use clock::Clock;
+pub use benchmark::{Benchmark, InputStrategy};
+pub use config::Config;
+pub use state::{BatchSize, State};
+pub use clock::{Calibration, Clock, ClockSource, Elapsed};
+pub use stats::Statistics;
+pub use reporter::{
+    ConsoleReporter, Context, CsvReporter, Format, GoogleJsonReporter, JsonReporter,
+    LibtestReporter, Reporter,
+};
+pub use result::{Aggregate, BenchmarkResult, RunType};
+pub use complexity::{Complexity, Fit};
+pub use counter::{Counter, CounterFlags};
+pub use arg_spec::ArgSpec;
+pub use fixture::Fixture;
+pub use baseline::{Comparison, Verdict};
+
+use std::hint;
+use std::process;
+use std::sync::atomic::{compiler_fence, Ordering};
 
 /// This method forces the compiler to not optimize the return statement of a benchmark.
 ///
-/// # Examples
+/// The value is passed through `std::hint::black_box`, so the compiler has to assume it is used
+/// and cannot elide the work that produced it. The value is returned so it can still be used
+/// afterwards.
 ///
-/// ``` use pew::{self, State};
+/// # Examples
 ///
-/// fn bm_simple(state: &mut State<u64>) { pew::do_not_optimize(5 + 10); } ```
+/// ```
+/// use pew::{self, State};
 ///
-/// TODO: Conditionally compile this only on nightly so the rest of the benchmark crate can be used
-/// in stable.
-pub fn do_not_optimize<T>(val: T) {
-    let mut _v = val;
-    unsafe {
-        asm!("" : "+r" (&_v) : : : "volatile");
-    }
+/// fn bm_simple(state: &mut State<u64>) {
+///     pew::do_not_optimize(5 + 10);
+/// }
+/// ```
+#[inline]
+pub fn do_not_optimize<T>(val: T) -> T {
+    hint::black_box(val)
 }
 
 /// This method forces the compiler to not optimize writes to memory in
 /// a benchmark.
 ///
+/// This acts as a compiler barrier that may read or write any memory the benchmark has made
+/// visible to the compiler (e.g. by passing a pointer to `do_not_optimize`), so pending writes
+/// to that memory have to be performed before it and cannot be elided.
+///
 /// # Examples
 ///
 /// ```
@@ -95,17 +159,52 @@ pub fn do_not_optimize<T>(val: T) {
 ///
 /// fn bm_simple(state: &mut State<u64>) {
 ///     let mut vec = Vec::new();
+///     pew::do_not_optimize(vec.as_ptr());
 ///     vec.push(1);
 ///     vec.push(2);
 ///     pew::clobber();
 /// }
 /// ```
-///
-/// TODO: Conditionally compile this only on nightly so the rest of the
-/// benchmark crate can be used in stable.
+#[inline]
 pub fn clobber() {
-    unsafe {
-        asm!("" : : : "memory" : "volatile");
+    compiler_fence(Ordering::SeqCst);
+    hint::black_box(());
+}
+
+/// Finishes the output of a benchmark program once every suite has run
+///
+/// This closes the document of `--format google_json` (see `GoogleJsonReporter::finish`), which
+/// is otherwise closed when the process exits. Then, with `--fail-if-slower` (see
+/// `Config::with_fail_if_slower`), every benchmark that regressed since the last call is printed
+/// to stderr, and the process exits with status 1 if there are any. Programs that only use
+/// `Benchmark::run` do not need to call this for their regressions to fail the process, but
+/// programs that use `Benchmark::run_with` do.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate pew;
+/// use pew::Benchmark;
+///
+/// fn bm_sum(state: &mut pew::State<u64>) {
+///     let n = state.get_input();
+///     pew::do_not_optimize((0..n).sum::<u64>());
+/// }
+///
+/// fn main() {
+///     Benchmark::with_name("sum")
+///         .with_range(64, 64, 2)
+///         .with_bench(pew_bench!(bm_sum))
+///         .run();
+///
+///     pew::finish();
+/// }
+/// ```
+pub fn finish() {
+    GoogleJsonReporter::finish();
+    if baseline::report_regressions() {
+        process::exit(1);
     }
 }
 
@@ -0,0 +1,1008 @@
+use clock::{Calibration, ClockSource, Elapsed};
+use counter::{Counter, CounterFlags};
+use host;
+use libc;
+use result::{Aggregate, BenchmarkResult, RunType};
+use serde_json::{self, Value};
+use stats::Statistics;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io::{self, Write};
+use std::sync::{Mutex, Once};
+
+lazy_static! {
+    /// The counter columns of the last CSV header that was printed, or `None` if there was none
+    static ref CSV_HEADER: Mutex<Option<Vec<String>>> = Mutex::new(None);
+    /// The state of the document printed by every `GoogleJsonReporter`, or `None` if it has not
+    /// been started
+    static ref GOOGLE_JSON: Mutex<Option<GoogleJsonDocument>> = Mutex::new(None);
+}
+
+static CLOSE_GOOGLE_JSON_AT_EXIT: Once = Once::new();
+
+/// Describes a suite of benchmarks, i.e. a single call to `Benchmark::run`
+#[derive(Clone, Debug)]
+pub struct Context {
+    /// The name of the `Benchmark`
+    pub name: String,
+    /// The clock the benchmarks are run with
+    pub clock: ClockSource,
+    /// The measured overhead of the clock
+    pub calibration: Calibration,
+    /// Whether `calibration`'s overhead is subtracted from every run
+    pub overhead_subtracted: bool,
+}
+
+/// Receives the results of a benchmark suite as they are produced
+///
+/// A suite corresponds to a single call to `Benchmark::run`. `begin_suite` is called before any
+/// benchmark in the suite is run, `benchmark` is called once for every bench function and
+/// argument after it has finished running, and `end_suite` is called after the last one.
+///
+/// # Examples
+///
+/// ```
+/// use pew::{BenchmarkResult, Reporter};
+///
+/// struct MeanOnly;
+///
+/// impl Reporter for MeanOnly {
+///     fn benchmark(&mut self, result: &BenchmarkResult) {
+///         println!("{} took {:.0}ns", result.name(), result.real_time.mean);
+///     }
+/// }
+/// ```
+pub trait Reporter {
+    /// Called before any of the bench functions of the `Benchmark` are run
+    fn begin_suite(&mut self, _context: &Context) {}
+
+    /// Called with the result of every benchmark in the suite as soon as it has finished
+    fn benchmark(&mut self, result: &BenchmarkResult);
+
+    /// Called after every benchmark in the suite has been reported
+    fn end_suite(&mut self) {}
+}
+
+/// The output formats that can be selected with `--format`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// An aligned, human readable table (see `ConsoleReporter`)
+    Console,
+    /// Comma separated values (see `CsvReporter`)
+    Csv,
+    /// One JSON document per suite (see `JsonReporter`)
+    Json,
+    /// A single JSON document in the format of google/benchmark (see `GoogleJsonReporter`)
+    GoogleJson,
+    /// The `test name ... bench:` lines of libtest (see `LibtestReporter`)
+    Libtest,
+}
+
+impl Format {
+    /// Parses the value passed to `--format`
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "console" => Some(Format::Console),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "google_json" => Some(Format::GoogleJson),
+            "libtest" => Some(Format::Libtest),
+            _ => None,
+        }
+    }
+
+    /// Returns a new reporter which outputs this format to stdout
+    pub fn reporter(self) -> Box<dyn Reporter> {
+        match self {
+            Format::Console => Box::new(ConsoleReporter::new()),
+            Format::Csv => Box::new(CsvReporter::new()),
+            Format::Json => Box::new(JsonReporter::new()),
+            Format::GoogleJson => Box::new(GoogleJsonReporter::new()),
+            Format::Libtest => Box::new(LibtestReporter::new()),
+        }
+    }
+}
+
+const COLUMNS: [&str; 16] = [
+    "Benchmark",
+    "Time",
+    "CPU",
+    "Min",
+    "Max",
+    "Median",
+    "Std Dev",
+    "CV",
+    "P90",
+    "P99",
+    "Runs",
+    "Iterations",
+    "Bytes/s",
+    "Items/s",
+    "Change",
+    "Verdict",
+];
+
+/// The index of the first throughput column. This and every later column is only shown if a
+/// benchmark in the suite fills it in.
+const THROUGHPUT_COLUMN: usize = 12;
+
+/// The columns the `CsvReporter` adds to suites that were compared to a baseline
+const COMPARISON_COLUMNS: [&str; 3] = ["Change (%)", "P-value", "Verdict"];
+
+/// Prints each suite as a table with right aligned columns
+///
+/// `Time` and `CPU` are the mean wall clock and CPU time, the remaining times describe the clock
+/// the benchmark was run with (see `ClockSource`). All times are per iteration (see
+/// `State::iter`). The table is preceded by a line describing the clock and its overhead.
+/// Aggregates of repeated benchmarks only fill in `Time`, `CPU` and `Runs` (the number of
+/// repetitions), and so do complexity fits (where `Runs` is the number of arguments).
+///
+/// `Bytes/s` and `Items/s` are only shown if a benchmark in the suite set them (see
+/// `State::set_bytes_processed`), with binary (KiB, MiB, ...) and decimal (k, M, ...) prefixes
+/// respectively. Every counter set by a benchmark in the suite (see `State::counter`) gets a
+/// column of its own after those, with decimal prefixes from `n` to `T`.
+///
+/// If a benchmark was compared to a baseline (see `Config::with_baseline`), `Change` shows the
+/// percent change of its mean time and `Verdict` whether it got faster or slower, along with the
+/// p-value of the change.
+///
+/// Rows are buffered until the end of the suite so that every column can be sized to fit its
+/// widest value.
+pub struct ConsoleReporter {
+    context: Option<Context>,
+    rows: Vec<(Vec<String>, BTreeMap<String, String>)>,
+}
+
+impl ConsoleReporter {
+    pub fn new() -> Self {
+        ConsoleReporter {
+            context: None,
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl Default for ConsoleReporter {
+    fn default() -> Self {
+        ConsoleReporter::new()
+    }
+}
+
+impl Reporter for ConsoleReporter {
+    fn begin_suite(&mut self, context: &Context) {
+        self.context = Some(context.clone());
+        self.rows.clear();
+    }
+
+    fn benchmark(&mut self, result: &BenchmarkResult) {
+        if let Some((real, cpu)) = result.value() {
+            let unit = match result.run_type {
+                RunType::Aggregate(Aggregate::Cv) | RunType::Rms(_) => "%".to_string(),
+                RunType::BigO(fit) => format!(" {}", fit.complexity.name()),
+                _ => " ns".to_string(),
+            };
+            let mut row = vec![
+                result.name(),
+                format!("{}{}", format_value(result, real), unit),
+                format!("{}{}", format_value(result, cpu), unit),
+            ];
+            row.extend(vec![String::new(); 7]);
+            row.push(result.runs.to_string());
+            row.push(String::new());
+            row.push(format_rate(result.bytes_per_second, &BYTE_UNITS));
+            row.push(format_rate(result.items_per_second, &ITEM_UNITS));
+            row.extend(vec![String::new(); 2]);
+            self.rows.push((row, counter_cells(result, format_counter)));
+            return;
+        }
+
+        let stats = result.stats();
+        let mut row = vec![
+            result.name(),
+            format!("{} ns", format_ns(result.real_time.mean)),
+            format!("{} ns", format_ns(result.cpu_time.mean)),
+            format!("{} ns", format_ns(stats.min)),
+            format!("{} ns", format_ns(stats.max)),
+            format!("{} ns", format_ns(stats.median)),
+            format!("{} ns", format_ns(stats.stddev)),
+            format!("{:.2}%", stats.cv * 100.0),
+            format!("{} ns", format_ns(stats.p90)),
+            format!("{} ns", format_ns(stats.p99)),
+            result.runs.to_string(),
+            result.iterations.to_string(),
+            format_rate(result.bytes_per_second, &BYTE_UNITS),
+            format_rate(result.items_per_second, &ITEM_UNITS),
+        ];
+        match result.comparison {
+            Some(ref comparison) => {
+                row.push(format!("{:+.2}%", comparison.change * 100.0));
+                row.push(format!(
+                    "{} (p={:.3})",
+                    comparison.verdict.name(),
+                    comparison.p_value
+                ));
+            }
+            None => row.extend(vec![String::new(); 2]),
+        }
+        self.rows.push((row, counter_cells(result, format_counter)));
+    }
+
+    fn end_suite(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let rows = &self.rows;
+        let shown: Vec<usize> = (0..COLUMNS.len())
+            .filter(|&c| c < THROUGHPUT_COLUMN || rows.iter().any(|(row, _)| !row[c].is_empty()))
+            .collect();
+        let counters = counter_names(rows);
+        let mut columns: Vec<&str> = shown.iter().map(|&c| COLUMNS[c]).collect();
+        columns.extend(counters.iter().map(|c| c.as_str()));
+        let rows: Vec<Vec<&str>> = rows
+            .iter()
+            .map(|(row, cells)| {
+                let mut row: Vec<&str> = shown.iter().map(|&c| row[c].as_str()).collect();
+                row.extend(
+                    counters
+                        .iter()
+                        .map(|c| cells.get(c).map_or("", |cell| cell.as_str())),
+                );
+                row
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = columns.iter().map(|c| c.len()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let format_row = |row: &[&str]| {
+            let mut line = format!("{:<width$}", row[0], width = widths[0]);
+            for (cell, width) in row[1..].iter().zip(&widths[1..]) {
+                line.push_str(&format!("  {:>width$}", cell, width = width));
+            }
+            line
+        };
+
+        let header = format_row(&columns);
+        let separator = "-".repeat(header.len());
+        if let Some(ref context) = self.context {
+            let calibration = &context.calibration;
+            println!(
+                "Clock: {} ({} ns resolution), overhead per run: {}, per pause/resume: {}{}",
+                context.clock.name(),
+                calibration.resolution,
+                format_overhead(calibration.read),
+                format_overhead(calibration.pause_resume),
+                if context.overhead_subtracted {
+                    " (subtracted)"
+                } else {
+                    ""
+                }
+            );
+        }
+        println!("{}", separator);
+        println!("{}", header);
+        println!("{}", separator);
+        for row in &rows {
+            println!("{}", format_row(row));
+        }
+    }
+}
+
+/// Prints one comma separated row per benchmark
+///
+/// The columns are the same as those of the `ConsoleReporter`. The header is printed once for the
+/// whole program, not once per suite, so that the output of several suites can be concatenated
+/// (and fed to `pew_transpose`). All times are in nanoseconds. Aggregates of repeated benchmarks
+/// and complexity fits leave every column but `Time`, `CPU` and `Runs` empty. `_cv` and `_RMS`
+/// rows are in percent, and `_BigO` rows contain the coefficient of the fitted complexity.
+/// `Bytes/s` and `Items/s` are empty unless the benchmark set them.
+///
+/// If a benchmark in the suite was compared to a baseline (see `Config::with_baseline`), the
+/// suite has `Change (%)`, `P-value` and `Verdict` columns after those. Every counter set by a
+/// benchmark in the suite (see `State::counter`) gets a column of its own after that, with the
+/// full precision of the counter. Since these columns can differ between suites, the header is
+/// printed again before a suite whose columns differ from those of the previous header. Rows are
+/// buffered until the end of the suite for this reason.
+pub struct CsvReporter {
+    rows: Vec<((String, String), BTreeMap<String, String>)>,
+}
+
+impl CsvReporter {
+    pub fn new() -> Self {
+        CsvReporter { rows: Vec::new() }
+    }
+}
+
+impl Default for CsvReporter {
+    fn default() -> Self {
+        CsvReporter::new()
+    }
+}
+
+impl Reporter for CsvReporter {
+    fn begin_suite(&mut self, _context: &Context) {
+        self.rows.clear();
+    }
+
+    fn benchmark(&mut self, result: &BenchmarkResult) {
+        // Counters can be far below 1, e.g. the seconds per iteration of a rate that is inverted
+        let counters = counter_cells(result, |counter| counter.value.to_string());
+        let bytes_per_second = result
+            .bytes_per_second
+            .map_or(String::new(), |r| format!("{:.0}", r));
+        let items_per_second = result
+            .items_per_second
+            .map_or(String::new(), |r| format!("{:.0}", r));
+
+        if let Some((real, cpu)) = result.value() {
+            let row = format!(
+                "{},{},{},,,,,,,,{},,{},{}",
+                result.name(),
+                format_value(result, real),
+                format_value(result, cpu),
+                result.runs,
+                bytes_per_second,
+                items_per_second
+            );
+            self.rows.push(((row, String::new()), counters));
+            return;
+        }
+
+        let stats = result.stats();
+        let row = format!(
+            "{},{},{},{},{},{},{},{:.2},{},{},{},{},{},{}",
+            result.name(),
+            format_ns(result.real_time.mean),
+            format_ns(result.cpu_time.mean),
+            format_ns(stats.min),
+            format_ns(stats.max),
+            format_ns(stats.median),
+            format_ns(stats.stddev),
+            stats.cv * 100.0,
+            format_ns(stats.p90),
+            format_ns(stats.p99),
+            result.runs,
+            result.iterations,
+            bytes_per_second,
+            items_per_second
+        );
+        let comparison = result
+            .comparison
+            .as_ref()
+            .map_or(String::new(), |comparison| {
+                format!(
+                    "{:.2},{:.6},{}",
+                    comparison.change * 100.0,
+                    comparison.p_value,
+                    comparison.verdict.name()
+                )
+            });
+        self.rows.push(((row, comparison), counters));
+    }
+
+    fn end_suite(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let compared = self
+            .rows
+            .iter()
+            .any(|((_, comparison), _)| !comparison.is_empty());
+        let counters = counter_names(&self.rows);
+        let mut columns: Vec<String> = Vec::new();
+        if compared {
+            columns.extend(COMPARISON_COLUMNS.iter().map(|c| c.to_string()));
+        }
+        columns.extend(counters.iter().cloned());
+        let mut header = CSV_HEADER.lock().unwrap();
+        if header.as_ref() != Some(&columns) {
+            let mut line = "Name,Time (ns),CPU (ns),Min (ns),Max (ns),Median (ns),Std Dev (ns),\
+                            CV (%),P90 (ns),P99 (ns),Runs,Iterations,Bytes/s,Items/s"
+                .to_string();
+            for column in &columns {
+                line.push(',');
+                line.push_str(column);
+            }
+            println!("{}", line);
+            *header = Some(columns);
+        }
+
+        for ((row, comparison), cells) in &self.rows {
+            let mut line = row.clone();
+            if compared {
+                line.push(',');
+                line.push_str(if comparison.is_empty() {
+                    ",,"
+                } else {
+                    comparison
+                });
+            }
+            for counter in &counters {
+                line.push(',');
+                line.push_str(cells.get(counter).map_or("", |cell| cell.as_str()));
+            }
+            println!("{}", line);
+        }
+    }
+}
+
+/// Prints each suite as a single line JSON document
+///
+/// Every suite produces one line of the form `{"suite": name, "context": {...}, "benchmarks":
+/// [...]}`, where `context` describes the clock and its overhead, so the output of a program
+/// running several suites is a stream of newline delimited JSON. Each entry
+/// in `benchmarks` has the fields of the `BenchmarkResult` (along with its full `name`), where
+/// `real_time` and `cpu_time` are objects with one field per `Statistics` field. Every entry has a
+/// `run_type` which is either `repetition` (with a `repetition_index`) or `aggregate` (with an
+/// `aggregate_name` and the aggregated `value`). Complexity fits are aggregates named `BigO`
+/// (which also have the fitted complexity as `big_o`) and `RMS`. All times are in nanoseconds.
+/// `comparison` is `null` unless the benchmark was compared to a baseline (see
+/// `Config::with_baseline`), in which case it has the fields of the `Comparison`. Like the other
+/// reporters, nothing is printed for a suite without any benchmarks (e.g. if `--filter` matched
+/// none of them).
+pub struct JsonReporter {
+    suite: String,
+    context: Value,
+    benchmarks: Vec<Value>,
+}
+
+impl JsonReporter {
+    pub fn new() -> Self {
+        JsonReporter {
+            suite: String::new(),
+            context: Value::Null,
+            benchmarks: Vec::new(),
+        }
+    }
+}
+
+impl Default for JsonReporter {
+    fn default() -> Self {
+        JsonReporter::new()
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn begin_suite(&mut self, context: &Context) {
+        let calibration = &context.calibration;
+        self.suite = context.name.clone();
+        self.context = json!({
+            "clock": context.clock.name(),
+            "resolution": calibration.resolution,
+            "read_overhead": elapsed_json(calibration.read),
+            "pause_resume_overhead": elapsed_json(calibration.pause_resume),
+            "overhead_subtracted": context.overhead_subtracted,
+        });
+        self.benchmarks.clear();
+    }
+
+    fn benchmark(&mut self, result: &BenchmarkResult) {
+        let mut benchmark = json!({
+            "name": result.name(),
+            "group": result.group,
+            "function": result.function,
+            "args": result.args,
+            "runs": result.runs,
+            "iterations": result.iterations,
+            "clock": result.clock.name(),
+            "real_time": stats_json(&result.real_time),
+            "cpu_time": stats_json(&result.cpu_time),
+            "real_samples": result.real_samples,
+            "cpu_samples": result.cpu_samples,
+            "repetitions": result.repetitions,
+            "bytes_per_second": result.bytes_per_second,
+            "items_per_second": result.items_per_second,
+            "counters": result
+                .counters
+                .iter()
+                .map(|(name, counter)| (name.clone(), json!(counter.value)))
+                .collect::<serde_json::Map<String, Value>>(),
+            "comparison": result.comparison.as_ref().map(|comparison| json!({
+                "baseline": comparison.baseline,
+                "baseline_mean": comparison.baseline_mean,
+                "change": comparison.change,
+                "interval": [comparison.interval.0, comparison.interval.1],
+                "p_value": comparison.p_value,
+                "verdict": comparison.verdict.name(),
+            })),
+        });
+        match result.run_type {
+            RunType::Repetition(index) => {
+                benchmark["run_type"] = json!("repetition");
+                benchmark["repetition_index"] = json!(index);
+            }
+            RunType::Aggregate(aggregate) => {
+                benchmark["run_type"] = json!("aggregate");
+                benchmark["aggregate_name"] = json!(aggregate.name());
+            }
+            RunType::BigO(fit) => {
+                benchmark["run_type"] = json!("aggregate");
+                benchmark["aggregate_name"] = json!("BigO");
+                benchmark["big_o"] = json!(fit.complexity.name());
+            }
+            RunType::Rms(_) => {
+                benchmark["run_type"] = json!("aggregate");
+                benchmark["aggregate_name"] = json!("RMS");
+            }
+        }
+        if let Some((real, cpu)) = result.value() {
+            benchmark["value"] = json!({"real": real, "cpu": cpu});
+        }
+        self.benchmarks.push(benchmark);
+    }
+
+    fn end_suite(&mut self) {
+        if self.benchmarks.is_empty() {
+            return;
+        }
+
+        let suite = json!({
+            "suite": self.suite,
+            "context": self.context,
+            "benchmarks": self.benchmarks,
+        });
+        println!(
+            "{}",
+            serde_json::to_string(&suite).expect("JSON serialization failed")
+        );
+    }
+}
+
+/// Prints a single JSON document in the format of google/benchmark's `--benchmark_format=json`
+///
+/// The document has a `context` describing the machine (its CPUs, caches and load) and a
+/// `benchmarks` array with one entry per `BenchmarkResult`, so that tools written for
+/// google/benchmark (like its `compare.py`) can read the output of pew. All times are in
+/// nanoseconds.
+///
+/// Repetitions are entries with a `run_type` of `iteration`, whose `iterations` is the total
+/// number of iterations of every run. Aggregates and complexity fits are entries with a `run_type`
+/// of `aggregate`, named after their `run_name` followed by `_mean`, `_BigO`, etc. like in
+/// google/benchmark. Counters are fields of their benchmark's entry.
+///
+/// Unlike the other reporters, the output of every suite in the program is part of the same
+/// document. It is started by the first suite and closed by `GoogleJsonReporter::finish` (or
+/// `pew::finish`), or otherwise when the process exits, so that it is always complete.
+pub struct GoogleJsonReporter {
+    /// The entry of every benchmark in the suite along with its family
+    benchmarks: Vec<(String, Value)>,
+}
+
+/// The part of the document of the `GoogleJsonReporter` that has been printed
+struct GoogleJsonDocument {
+    /// The number of benchmarks printed so far
+    benchmarks: usize,
+    /// The name of every benchmark family (i.e. bench function) along with the `run_name` of
+    /// every instance of it, in the order they were printed
+    families: Vec<(String, Vec<String>)>,
+}
+
+impl GoogleJsonDocument {
+    /// Returns the `family_index` and `per_family_instance_index` of a benchmark
+    fn indices(&mut self, family: String, run_name: &str) -> (usize, usize) {
+        let family_index = match self.families.iter().position(|(f, _)| *f == family) {
+            Some(index) => index,
+            None => {
+                self.families.push((family, Vec::new()));
+                self.families.len() - 1
+            }
+        };
+        let instances = &mut self.families[family_index].1;
+        let instance_index = match instances.iter().position(|r| r == run_name) {
+            Some(index) => index,
+            None => {
+                instances.push(run_name.to_string());
+                instances.len() - 1
+            }
+        };
+        (family_index, instance_index)
+    }
+}
+
+impl GoogleJsonReporter {
+    pub fn new() -> Self {
+        GoogleJsonReporter {
+            benchmarks: Vec::new(),
+        }
+    }
+
+    /// Closes the document once every suite has been reported (`pew::finish` calls this)
+    ///
+    /// Suites reported after this start a new document. Does nothing if no suite has been
+    /// reported since the document was last closed. A document that is still open when the
+    /// process exits is closed then, so this only matters to programs that print more after
+    /// their benchmarks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, GoogleJsonReporter, State};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_sum(state: &mut State<u64>) {
+    ///     let n = state.get_input();
+    ///     pew::do_not_optimize((0..n).sum::<u64>());
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     for &name in &["sum", "sum_again"] {
+    ///         Benchmark::with_name(name)
+    ///             .with_range(64, 64, 2)
+    ///             .with_bench(pew_bench!(bm_sum))
+    ///             .with_reporter(GoogleJsonReporter::new())
+    ///             .run_with(&config);
+    ///     }
+    ///     GoogleJsonReporter::finish();
+    /// }
+    /// ```
+    pub fn finish() {
+        if GOOGLE_JSON.lock().unwrap().take().is_some() {
+            println!("\n  ]\n}}");
+        }
+    }
+
+    /// Returns the `context` of the document
+    fn context() -> Value {
+        let caches: Vec<Value> = host::caches()
+            .iter()
+            .map(|cache| {
+                json!({
+                    "type": cache.kind,
+                    "level": cache.level,
+                    "size": cache.size,
+                    "num_sharing": cache.num_sharing,
+                })
+            })
+            .collect();
+        json!({
+            "date": host::date(),
+            "host_name": host::host_name(),
+            "executable": env::args().next().unwrap_or_default(),
+            "num_cpus": host::num_cpus(),
+            "mhz_per_cpu": host::mhz_per_cpu(),
+            "cpu_scaling_enabled": host::cpu_scaling_enabled(),
+            "caches": caches,
+            "load_avg": host::load_avg(),
+            "library_build_type": if cfg!(debug_assertions) { "debug" } else { "release" },
+        })
+    }
+}
+
+impl Default for GoogleJsonReporter {
+    fn default() -> Self {
+        GoogleJsonReporter::new()
+    }
+}
+
+/// Closes the document of the `GoogleJsonReporter` when the process exits, like
+/// `GoogleJsonReporter::finish` but without panicking
+extern "C" fn close_google_json() {
+    if let Ok(mut document) = GOOGLE_JSON.lock() {
+        if document.take().is_some() {
+            // Nothing can be done about a closed stdout at this point
+            let mut stdout = io::stdout();
+            let _ = writeln!(stdout, "\n  ]\n}}");
+            let _ = stdout.flush();
+        }
+    }
+}
+
+/// Indents every line of `json` by `indent` spaces
+fn indent(json: &str, indent: usize) -> String {
+    let indent = " ".repeat(indent);
+    json.lines()
+        .map(|line| format!("{}{}", indent, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Reporter for GoogleJsonReporter {
+    fn begin_suite(&mut self, _context: &Context) {
+        self.benchmarks.clear();
+    }
+
+    fn benchmark(&mut self, result: &BenchmarkResult) {
+        let run_name = result.run_name();
+        // The indices are only known once the benchmarks of earlier suites have been printed
+        let mut benchmark = json!({
+            "name": result.name(),
+            "family_index": null,
+            "per_family_instance_index": null,
+            "run_name": run_name,
+            "run_type": "aggregate",
+            "repetitions": result.repetitions,
+            "threads": result.threads.unwrap_or(1),
+        });
+        match result.run_type {
+            RunType::Repetition(index) => {
+                benchmark["run_type"] = json!("iteration");
+                benchmark["repetition_index"] = json!(index);
+                benchmark["iterations"] = json!(result.runs * result.iterations);
+                benchmark["real_time"] = json!(result.real_time.mean);
+                benchmark["cpu_time"] = json!(result.cpu_time.mean);
+                benchmark["time_unit"] = json!("ns");
+                if let Some(rate) = result.bytes_per_second {
+                    benchmark["bytes_per_second"] = json!(rate);
+                }
+                if let Some(rate) = result.items_per_second {
+                    benchmark["items_per_second"] = json!(rate);
+                }
+                for (name, counter) in &result.counters {
+                    benchmark[name] = json!(counter.value);
+                }
+            }
+            RunType::Aggregate(aggregate) => {
+                let (real, cpu) = result.value().expect("aggregates have a value");
+                benchmark["aggregate_name"] = json!(aggregate.name());
+                benchmark["aggregate_unit"] = json!(match aggregate {
+                    Aggregate::Cv => "percentage",
+                    _ => "time",
+                });
+                benchmark["iterations"] = json!(result.runs);
+                benchmark["real_time"] = json!(real);
+                benchmark["cpu_time"] = json!(cpu);
+                benchmark["time_unit"] = json!("ns");
+            }
+            RunType::BigO(fit) => {
+                benchmark["aggregate_name"] = json!("BigO");
+                benchmark["aggregate_unit"] = json!("time");
+                benchmark["cpu_coefficient"] = json!(fit.cpu_coefficient);
+                benchmark["real_coefficient"] = json!(fit.real_coefficient);
+                benchmark["big_o"] = json!(fit.complexity.name());
+                benchmark["time_unit"] = json!("ns");
+            }
+            RunType::Rms(fit) => {
+                benchmark["aggregate_name"] = json!("RMS");
+                benchmark["aggregate_unit"] = json!("percentage");
+                benchmark["rms"] = json!(match result.clock {
+                    ClockSource::Monotonic => fit.real_rms,
+                    _ => fit.cpu_rms,
+                });
+            }
+        }
+        let family = format!("{}/{}", result.group, result.function);
+        self.benchmarks.push((family, benchmark));
+    }
+
+    fn end_suite(&mut self) {
+        if self.benchmarks.is_empty() {
+            return;
+        }
+
+        let mut document = GOOGLE_JSON.lock().unwrap();
+        if document.is_none() {
+            println!("{{");
+            let context = serde_json::to_string_pretty(&GoogleJsonReporter::context())
+                .expect("JSON serialization failed");
+            println!("  \"context\": {},", indent(&context, 2).trim_start());
+            print!("  \"benchmarks\": [");
+            *document = Some(GoogleJsonDocument {
+                benchmarks: 0,
+                families: Vec::new(),
+            });
+            CLOSE_GOOGLE_JSON_AT_EXIT.call_once(|| unsafe {
+                libc::atexit(close_google_json);
+            });
+        }
+        let document = document.as_mut().expect("the document was just started");
+
+        for (family, mut benchmark) in self.benchmarks.drain(..) {
+            let (family_index, instance_index) =
+                document.indices(family, benchmark["run_name"].as_str().unwrap_or_default());
+            benchmark["family_index"] = json!(family_index);
+            benchmark["per_family_instance_index"] = json!(instance_index);
+
+            let entry =
+                serde_json::to_string_pretty(&benchmark).expect("JSON serialization failed");
+            let separator = if document.benchmarks == 0 { "" } else { "," };
+            print!("{}\n{}", separator, indent(&entry, 4));
+            document.benchmarks += 1;
+        }
+        io::stdout().flush().expect("Failed to flush stdout");
+    }
+}
+
+/// Prints the results like the benchmark harness of libtest (i.e. `cargo bench` without
+/// `harness = false`)
+///
+/// Every repetition is printed as a line of the form `test name ... bench: 1,234 ns/iter (+/- 56)`
+/// with the median time of an iteration and the difference between the slowest and fastest
+/// iterations, as measured by the clock the benchmark was run with (see `ClockSource`). If the
+/// benchmark set its bytes processed (see `State::set_bytes_processed`), the line ends with the
+/// throughput, e.g. `= 512 MB/s`. Aggregates and complexity fits are skipped since libtest has
+/// nothing like them.
+///
+/// Like libtest, the benchmarks are preceded by a `running N tests` line and followed by a `test
+/// result` line, so that tools which parse the output of `cargo bench` can parse the output of
+/// pew. Unlike libtest, which prints these once for the whole binary, they are printed for every
+/// suite (i.e. every call to `Benchmark::run`), since the number of benchmarks a program runs is
+/// only known once it has run them.
+pub struct LibtestReporter {
+    /// The name and result of every repetition in the suite
+    rows: Vec<(String, String)>,
+}
+
+impl LibtestReporter {
+    pub fn new() -> Self {
+        LibtestReporter { rows: Vec::new() }
+    }
+}
+
+impl Default for LibtestReporter {
+    fn default() -> Self {
+        LibtestReporter::new()
+    }
+}
+
+impl Reporter for LibtestReporter {
+    fn begin_suite(&mut self, _context: &Context) {
+        self.rows.clear();
+    }
+
+    fn benchmark(&mut self, result: &BenchmarkResult) {
+        // Only aggregates and complexity fits have a value
+        if result.value().is_some() {
+            return;
+        }
+
+        let stats = result.stats();
+        let mut bench = format!(
+            "{:>11} ns/iter (+/- {})",
+            format_thousands(stats.median.round() as u64),
+            format_thousands((stats.max - stats.min).round() as u64)
+        );
+        if let Some(rate) = result.bytes_per_second {
+            bench.push_str(&format!(" = {} MB/s", (rate / 1e6) as u64));
+        }
+        self.rows.push((result.name(), bench));
+    }
+
+    fn end_suite(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let width = self
+            .rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        println!();
+        let plural = if self.rows.len() == 1 { "" } else { "s" };
+        println!("running {} test{}", self.rows.len(), plural);
+        for (name, bench) in &self.rows {
+            println!("test {:<width$} ... bench: {}", name, bench, width = width);
+        }
+        println!();
+        println!(
+            "test result: ok. 0 passed; 0 failed; 0 ignored; {} measured; 0 filtered out",
+            self.rows.len()
+        );
+        println!();
+    }
+}
+
+/// Formats an integer with commas between every 3 digits, e.g. `1,234`
+fn format_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats a time in nanoseconds with more precision for the short times of tight iterations
+fn format_ns(ns: f64) -> String {
+    if ns < 10.0 {
+        format!("{:.2}", ns)
+    } else if ns < 100.0 {
+        format!("{:.1}", ns)
+    } else {
+        format!("{:.0}", ns)
+    }
+}
+
+/// Formats the value of an aggregate, in nanoseconds, percent (for ratios) or as the coefficient
+/// of a complexity fit
+fn format_value(result: &BenchmarkResult, value: f64) -> String {
+    match result.run_type {
+        RunType::Aggregate(Aggregate::Cv) | RunType::Rms(_) => format!("{:.2}", value * 100.0),
+        RunType::BigO(_) if value < 1.0 => format!("{:.4}", value),
+        _ => format_ns(value),
+    }
+}
+
+/// Returns the formatted value of every counter of `result`
+fn counter_cells(
+    result: &BenchmarkResult,
+    format: fn(&Counter) -> String,
+) -> BTreeMap<String, String> {
+    result
+        .counters
+        .iter()
+        .map(|(name, counter)| (name.clone(), format(counter)))
+        .collect()
+}
+
+/// Returns the names of every counter in `rows`, in alphabetical order
+fn counter_names<T>(rows: &[(T, BTreeMap<String, String>)]) -> Vec<String> {
+    let names: BTreeSet<&String> = rows.iter().flat_map(|(_, cells)| cells.keys()).collect();
+    names.into_iter().cloned().collect()
+}
+
+/// Formats a counter for the console, e.g. `1.25M`, `3.40k/s` or `250ns`
+fn format_counter(counter: &Counter) -> String {
+    let rate = counter.flags.contains(CounterFlags::RATE);
+    let suffix = match (rate, counter.flags.contains(CounterFlags::INVERT)) {
+        (true, false) => "/s",
+        (true, true) => "s",
+        _ => "",
+    };
+    format!("{}{}", format_scaled(counter.value, &COUNT_UNITS), suffix)
+}
+
+/// The units of `format_scaled`: each unit is `base` times the previous one, and the unit at the
+/// index is that of the value itself
+type Units = (f64, &'static [&'static str], usize);
+
+const COUNT_UNITS: Units = (1000.0, &["n", "\u{b5}", "m", "", "k", "M", "G", "T"], 3);
+const BYTE_UNITS: Units = (1024.0, &[" B/s", " KiB/s", " MiB/s", " GiB/s", " TiB/s"], 0);
+const ITEM_UNITS: Units = (1000.0, &["/s", "k/s", "M/s", "G/s", "T/s"], 0);
+
+/// Formats a rate with `format_scaled`, or as an empty cell if there is none
+fn format_rate(rate: Option<f64>, units: &Units) -> String {
+    rate.map_or(String::new(), |rate| format_scaled(rate, units))
+}
+
+/// Formats a positive value with the unit that keeps it between 1 and `base`, or with the
+/// smallest or largest unit if there is none
+fn format_scaled(value: f64, &(base, units, mut unit): &Units) -> String {
+    let mut scaled = value;
+    while scaled >= base && unit < units.len() - 1 {
+        scaled /= base;
+        unit += 1;
+    }
+    while scaled > 0.0 && scaled < 1.0 && unit > 0 {
+        scaled *= base;
+        unit -= 1;
+    }
+    format!("{}{}", format_ns(scaled), units[unit])
+}
+
+fn format_overhead(overhead: Elapsed) -> String {
+    format!("{} ns real / {} ns CPU", overhead.real, overhead.cpu)
+}
+
+fn elapsed_json(elapsed: Elapsed) -> Value {
+    json!({
+        "real": elapsed.real,
+        "cpu": elapsed.cpu,
+    })
+}
+
+pub(crate) fn stats_json(stats: &Statistics) -> Value {
+    json!({
+        "mean": stats.mean,
+        "min": stats.min,
+        "max": stats.max,
+        "median": stats.median,
+        "stddev": stats.stddev,
+        "cv": stats.cv,
+        "p90": stats.p90,
+        "p99": stats.p99,
+    })
+}
@@ -0,0 +1,383 @@
+use baseline::{Baseline, Comparison};
+use clock::{ClockSource, Elapsed};
+use complexity::Fit;
+use counter::Counter;
+use stats::Statistics;
+use std::collections::BTreeMap;
+
+/// A statistic computed across the repetitions of a benchmark (see `--repetitions`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Mean,
+    Median,
+    StdDev,
+    /// The coefficient of variation, which is a ratio rather than a time
+    Cv,
+}
+
+impl Aggregate {
+    /// Every aggregate, in the order they are reported
+    pub const ALL: [Aggregate; 4] = [
+        Aggregate::Mean,
+        Aggregate::Median,
+        Aggregate::StdDev,
+        Aggregate::Cv,
+    ];
+
+    /// Returns the suffix appended to the name of the benchmark, e.g. `stddev`
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+            Aggregate::StdDev => "stddev",
+            Aggregate::Cv => "cv",
+        }
+    }
+
+    /// Returns this aggregate of `stats`
+    pub fn of(self, stats: &Statistics) -> f64 {
+        match self {
+            Aggregate::Mean => stats.mean,
+            Aggregate::Median => stats.median,
+            Aggregate::StdDev => stats.stddev,
+            Aggregate::Cv => stats.cv,
+        }
+    }
+}
+
+/// Whether a `BenchmarkResult` is a measurement or an aggregate of measurements
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunType {
+    /// The measurement of the repetition with this (zero based) index
+    Repetition(u64),
+    /// An aggregate over the measurements of every repetition
+    Aggregate(Aggregate),
+    /// The complexity fitted over every argument of a bench function, whose value is the
+    /// coefficient of the fit (see `Benchmark::with_complexity`)
+    BigO(Fit),
+    /// The normalized RMS error of the fit over every argument of a bench function
+    Rms(Fit),
+}
+
+/// The result of running a single bench function with a single argument
+///
+/// One of these is produced for every repetition of every benchmark that passes the `--filter`,
+/// followed by one per `Aggregate` if the benchmark was repeated. They are passed to the
+/// `Reporter` and returned from `Benchmark::run`.
+///
+/// For aggregates, every sample is the mean of a repetition, so `runs` is the number of
+/// repetitions, `iterations` is 1, and `real_time` and `cpu_time` describe the spread of the
+/// repetitions. Use `value` to get the aggregate itself. Complexity fits work the same way, except
+/// that every sample is the mean time of an argument and `args` is empty.
+#[derive(Clone, Debug)]
+pub struct BenchmarkResult {
+    /// The name of the `Benchmark` the bench function belongs to
+    pub group: String,
+    /// The name of the bench function
+    pub function: String,
+    /// The arguments (i.e. `i` from the range, or one value per range for `with_ranges`) the
+    /// bench function was run with
+    pub args: Vec<u64>,
+    /// The number of threads the bench function was run on, if it was set with
+    /// `Benchmark::with_threads` or `Benchmark::with_thread_range`
+    pub threads: Option<u64>,
+    /// The number of times the bench function was run
+    pub runs: u64,
+    /// The number of iterations in each run. This is 1 unless the bench function uses
+    /// `State::iter`.
+    pub iterations: u64,
+    /// The clock that decided how many runs were needed
+    pub clock: ClockSource,
+    /// The wall clock time in nanoseconds of a single iteration of each run, in the order they
+    /// were run
+    pub real_samples: Vec<f64>,
+    /// The CPU time in nanoseconds of a single iteration of each run, in the order they were run
+    pub cpu_samples: Vec<f64>,
+    /// Statistics computed over `real_samples`
+    pub real_time: Statistics,
+    /// Statistics computed over `cpu_samples`
+    pub cpu_time: Statistics,
+    /// Whether this is a repetition or an aggregate
+    pub run_type: RunType,
+    /// The number of times the benchmark was repeated
+    pub repetitions: u64,
+    /// The number of bytes processed per second of the clock (see `State::set_bytes_processed`)
+    pub bytes_per_second: Option<f64>,
+    /// The number of items processed per second of the clock (see `State::set_items_processed`)
+    pub items_per_second: Option<f64>,
+    /// The user defined counters of the benchmark (see `State::counter`), with their flags
+    /// already applied
+    pub counters: BTreeMap<String, Counter>,
+    /// How this compares to the same benchmark in the baseline selected with `--baseline`, if
+    /// there is one. Only repetitions are compared.
+    pub comparison: Option<Comparison>,
+}
+
+impl BenchmarkResult {
+    pub(crate) fn new(
+        group: &str,
+        function: &str,
+        args: &[u64],
+        clock: ClockSource,
+        iterations: u64,
+        samples: &[Elapsed],
+    ) -> Self {
+        let per_iteration = |t: u64| t as f64 / iterations as f64;
+        let real_samples: Vec<f64> = samples.iter().map(|s| per_iteration(s.real)).collect();
+        let cpu_samples: Vec<f64> = samples.iter().map(|s| per_iteration(s.cpu)).collect();
+        BenchmarkResult {
+            group: group.to_string(),
+            function: function.to_string(),
+            args: args.to_vec(),
+            threads: None,
+            runs: samples.len() as u64,
+            iterations,
+            clock,
+            real_time: Statistics::from_samples(&real_samples),
+            cpu_time: Statistics::from_samples(&cpu_samples),
+            real_samples,
+            cpu_samples,
+            run_type: RunType::Repetition(0),
+            repetitions: 1,
+            bytes_per_second: None,
+            items_per_second: None,
+            counters: BTreeMap::new(),
+            comparison: None,
+        }
+    }
+
+    /// Sets the number of threads the bench function was run on
+    pub(crate) fn with_threads(mut self, threads: Option<u64>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Marks this as the repetition with index `repetition` out of `repetitions`
+    pub(crate) fn with_repetition(mut self, repetition: u64, repetitions: u64) -> Self {
+        self.run_type = RunType::Repetition(repetition);
+        self.repetitions = repetitions;
+        self
+    }
+
+    /// Sets the throughput given the total number of bytes and items processed by every run
+    ///
+    /// There is no throughput if the runs took no time at all, e.g. once the overhead of the
+    /// clock was subtracted (see `Config::with_subtract_overhead`).
+    pub(crate) fn with_processed(mut self, bytes: Option<u64>, items: Option<u64>) -> Self {
+        let seconds = self.seconds();
+        if seconds == 0.0 {
+            return self;
+        }
+        self.bytes_per_second = bytes.map(|b| b as f64 / seconds);
+        self.items_per_second = items.map(|i| i as f64 / seconds);
+        self
+    }
+
+    /// Compares this to the same benchmark in `baseline`, if there is one
+    pub(crate) fn with_baseline(mut self, baseline: Option<&Baseline>) -> Self {
+        self.comparison = baseline.and_then(|baseline| baseline.compare(&self));
+        self
+    }
+
+    /// Sets the counters given the sum of their values over every run, leaving out the rates of
+    /// runs that took no time at all (see `Counter::finish`)
+    pub(crate) fn with_counters(mut self, counters: Vec<(String, Counter)>) -> Self {
+        let seconds = self.seconds();
+        for (name, counter) in counters {
+            if let Some(counter) = counter.finish(self.runs, self.iterations, seconds) {
+                self.counters.insert(name, counter);
+            }
+        }
+        self
+    }
+
+    /// Returns the total time of every run, in seconds of the clock
+    fn seconds(&self) -> f64 {
+        let samples = match self.clock {
+            ClockSource::Monotonic => &self.real_samples,
+            _ => &self.cpu_samples,
+        };
+        samples.iter().sum::<f64>() * self.iterations as f64 / 1e9
+    }
+
+    /// Returns `aggregate` over the means of `repetitions`, which must all be of the same
+    /// benchmark
+    pub(crate) fn aggregate(repetitions: &[BenchmarkResult], aggregate: Aggregate) -> Self {
+        let first = &repetitions[0];
+        let real_samples: Vec<f64> = repetitions.iter().map(|r| r.real_time.mean).collect();
+        let cpu_samples: Vec<f64> = repetitions.iter().map(|r| r.cpu_time.mean).collect();
+        // The coefficient of variation of a rate or counter is not one, so it is left out
+        let rate = |per_second: fn(&BenchmarkResult) -> Option<f64>| {
+            let rates: Option<Vec<f64>> = repetitions.iter().map(per_second).collect();
+            match aggregate {
+                Aggregate::Cv => None,
+                _ => rates.map(|rates| aggregate.of(&Statistics::from_samples(&rates))),
+            }
+        };
+        BenchmarkResult {
+            group: first.group.clone(),
+            function: first.function.clone(),
+            args: first.args.clone(),
+            threads: first.threads,
+            runs: repetitions.len() as u64,
+            iterations: 1,
+            clock: first.clock,
+            real_time: Statistics::from_samples(&real_samples),
+            cpu_time: Statistics::from_samples(&cpu_samples),
+            real_samples,
+            cpu_samples,
+            run_type: RunType::Aggregate(aggregate),
+            repetitions: repetitions.len() as u64,
+            bytes_per_second: rate(|r| r.bytes_per_second),
+            items_per_second: rate(|r| r.items_per_second),
+            counters: first
+                .counters
+                .iter()
+                .filter_map(|(name, counter)| {
+                    let values: Option<Vec<f64>> = repetitions
+                        .iter()
+                        .map(|r| r.counters.get(name).map(|c| c.value))
+                        .collect();
+                    let value = match aggregate {
+                        Aggregate::Cv => None,
+                        _ => values.map(|values| aggregate.of(&Statistics::from_samples(&values))),
+                    };
+                    value.map(|value| (name.clone(), Counter::new(value, counter.flags)))
+                })
+                .collect(),
+            comparison: None,
+        }
+    }
+
+    /// Returns the result of a complexity fit (`run_type` must be `BigO` or `Rms`) of the mean
+    /// times measured for every value of the first argument of a bench function, with the other
+    /// arguments set to `args`
+    pub(crate) fn fit(
+        group: &str,
+        function: &str,
+        args: &[u64],
+        clock: ClockSource,
+        real_samples: Vec<f64>,
+        cpu_samples: Vec<f64>,
+        run_type: RunType,
+    ) -> Self {
+        BenchmarkResult {
+            group: group.to_string(),
+            function: function.to_string(),
+            args: args.to_vec(),
+            threads: None,
+            runs: real_samples.len() as u64,
+            iterations: 1,
+            clock,
+            real_time: Statistics::from_samples(&real_samples),
+            cpu_time: Statistics::from_samples(&cpu_samples),
+            real_samples,
+            cpu_samples,
+            run_type,
+            repetitions: 1,
+            bytes_per_second: None,
+            items_per_second: None,
+            counters: BTreeMap::new(),
+            comparison: None,
+        }
+    }
+
+    /// Returns the full name of the benchmark, e.g. `group/fn/1024`, `group/fn/1024/8` or
+    /// `group/fn/1024/threads:4`
+    ///
+    /// This is the name that `--filter` is matched against. Aggregates have the name of the
+    /// aggregate appended, e.g. `group/fn/1024_mean`. Complexity fits are named after the bench
+    /// function, e.g. `group/fn_BigO` and `group/fn_RMS`, and if it has several arguments after
+    /// the others as well, with `N` in place of the first one, e.g. `group/fn/N/8_BigO`.
+    pub fn name(&self) -> String {
+        let name = self.run_name();
+        match self.run_type {
+            RunType::Repetition(_) => name,
+            RunType::Aggregate(aggregate) => format!("{}_{}", name, aggregate.name()),
+            RunType::BigO(_) => format!("{}_BigO", name),
+            RunType::Rms(_) => format!("{}_RMS", name),
+        }
+    }
+
+    /// Returns the name of the benchmark without the suffix of its aggregate or complexity fit
+    pub(crate) fn run_name(&self) -> String {
+        match self.run_type {
+            RunType::BigO(_) | RunType::Rms(_) if !self.args.is_empty() => {
+                let function = format!("{}/N", self.function);
+                BenchmarkResult::format_name(&self.group, &function, &self.args, self.threads)
+            }
+            _ => {
+                BenchmarkResult::format_name(&self.group, &self.function, &self.args, self.threads)
+            }
+        }
+    }
+
+    /// Returns the name of the benchmark of `function` in `group` run with `args` on `threads`
+    /// threads
+    pub(crate) fn format_name(
+        group: &str,
+        function: &str,
+        args: &[u64],
+        threads: Option<u64>,
+    ) -> String {
+        let mut name = format!("{}/{}", group, function);
+        for arg in args {
+            name.push_str(&format!("/{}", arg));
+        }
+        if let Some(threads) = threads {
+            name.push_str(&format!("/threads:{}", threads));
+        }
+        name
+    }
+
+    /// Returns the aggregated wall clock and CPU time, or `None` if this is not an aggregate
+    ///
+    /// These are times in nanoseconds except for `Aggregate::Cv` and `RunType::Rms`, which are
+    /// ratios, and `RunType::BigO`, which is the coefficient of the fitted complexity.
+    pub fn value(&self) -> Option<(f64, f64)> {
+        match self.run_type {
+            RunType::Repetition(_) => None,
+            RunType::Aggregate(aggregate) => {
+                Some((aggregate.of(&self.real_time), aggregate.of(&self.cpu_time)))
+            }
+            RunType::BigO(fit) => Some((fit.real_coefficient, fit.cpu_coefficient)),
+            RunType::Rms(fit) => Some((fit.real_rms, fit.cpu_rms)),
+        }
+    }
+
+    /// Returns the statistics of the clock that decided how many runs were needed
+    ///
+    /// This is `real_time` for `ClockSource::Monotonic` and `cpu_time` otherwise.
+    pub fn stats(&self) -> &Statistics {
+        match self.clock {
+            ClockSource::Monotonic => &self.real_time,
+            _ => &self.cpu_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aggregate, BenchmarkResult};
+    use clock::{ClockSource, Elapsed};
+
+    #[test]
+    fn runs_that_took_no_time_have_no_throughput() {
+        let samples = [Elapsed::default(); 3];
+        let repetitions: Vec<BenchmarkResult> = (0..3)
+            .map(|repetition| {
+                BenchmarkResult::new("group", "bm", &[1], ClockSource::Monotonic, 1, &samples)
+                    .with_repetition(repetition, 3)
+                    .with_processed(Some(0), Some(0))
+            })
+            .collect();
+        assert_eq!(repetitions[0].bytes_per_second, None);
+        assert_eq!(repetitions[0].items_per_second, None);
+
+        for aggregate in &Aggregate::ALL {
+            let result = BenchmarkResult::aggregate(&repetitions, *aggregate);
+            assert_eq!(result.bytes_per_second, None);
+            assert_eq!(result.items_per_second, None);
+        }
+    }
+}
@@ -1,27 +1,425 @@
-use clock::Clock;
+use clock::{Clock, ClockSource, Elapsed};
+use counter::Counter;
+use std::cmp;
 use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// The input of a single run, see `InputStrategy`
+pub(crate) enum Input<T> {
+    /// The run has an input of its own, which the bench function can take or modify freely
+    Owned(T),
+    /// The run shares the input with every other run. It is cloned (with the given function) the
+    /// first time the bench function asks for it mutably.
+    Shared(Arc<T>, fn(&T) -> T),
+    /// The run borrows the fixture of the benchmark, which must be given back once it is over
+    /// (see `Benchmark::with_fixture`)
+    Fixture(T),
+}
+
+/// How many inputs `State::iter_batched` sets up at once
+///
+/// Every batch costs a pause and resume of the timer, so larger batches add less overhead to the
+/// measured time. On the other hand, every input of a batch is alive at the same time, so large
+/// inputs need smaller batches to fit in memory (and cache).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchSize {
+    /// Inputs that are cheap to keep around. The iterations of a run are split into 10 batches.
+    SmallInput,
+    /// Inputs that take up a lot of memory. The iterations of a run are split into 1000 batches.
+    LargeInput,
+    /// Set up a single input at a time. This pauses the timer for every iteration, so it should
+    /// only be used for routines that take much longer than the overhead of the clock.
+    PerIteration,
+}
+
+impl BatchSize {
+    /// Returns the number of inputs to set up at once for a run of `iterations` iterations
+    fn batch_size(self, iterations: u64) -> u64 {
+        match self {
+            BatchSize::SmallInput => iterations.div_ceil(10),
+            BatchSize::LargeInput => iterations.div_ceil(1000),
+            BatchSize::PerIteration => 1,
+        }
+    }
+}
 
 /// The benchmark state
 ///
 /// At a high level, it allows one to pause/resume the timer and also access an argument for this
 /// run of the benchmark.
 ///
-/// `T` will either be `u64` in the case a generator is not specified, or a user defined `T: Clone
-/// + Default` if a generator(s) is defined (where `T` is the return type of the final specified
-/// generator).
+/// `T` will either be `u64` in the case a generator is not specified, or a user defined
+/// `T: Clone` if a generator(s) is defined (where `T` is the return type of the final specified
+/// generator), or a `Fixture`. The input can be borrowed with `input` and `input_mut`, or moved
+/// out with `get_input` if `T: Default`.
+///
+/// A bench function can either do its work once per call, or loop over `iter` and do it once per
+/// iteration. In the latter case, pew picks the number of iterations so that each run takes long
+/// enough to be measured accurately, and reports the time per iteration.
 pub struct State<T> {
     clock: Clock,
-    input: T,
+    input: Input<T>,
+    iterations: u64,
+    thread_index: u64,
+    thread_count: u64,
+    uses_iterations: bool,
+    pauses: u64,
+    bytes_processed: Option<u64>,
+    items_processed: Option<u64>,
+    counters: Vec<(String, Counter)>,
 }
 
 impl<T> State<T> {
     pub fn new(input: T) -> State<T> {
+        State::for_run(Input::Owned(input), ClockSource::ProcessCpu, 1)
+    }
+
+    pub(crate) fn for_run(input: Input<T>, source: ClockSource, iterations: u64) -> State<T> {
         State {
-            clock: Clock::new(),
-            input: input,
+            clock: Clock::with_source(source),
+            input,
+            iterations,
+            thread_index: 0,
+            thread_count: 1,
+            uses_iterations: false,
+            pauses: 0,
+            bytes_processed: None,
+            items_processed: None,
+            counters: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the input
+    ///
+    /// Unlike `get_input`, this neither requires `T: Default` nor moves the input out of the
+    /// state, which together with `InputStrategy::Shared` lets read-only bench functions use huge
+    /// inputs without copying them for every run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{self, State};
+    ///
+    /// fn bm_sum(state: &mut State<Vec<u64>>) {
+    ///     pew::do_not_optimize(state.input().iter().sum::<u64>());
+    /// }
+    /// ```
+    pub fn input(&self) -> &T {
+        match self.input {
+            Input::Owned(ref input) | Input::Fixture(ref input) => input,
+            Input::Shared(ref input, _) => input,
+        }
+    }
+
+    /// Returns a mutable reference to the input
+    ///
+    /// If the input is shared between runs (see `InputStrategy::Shared`), it is cloned the first
+    /// time this is called in a run. The timer is paused while it is cloned.
+    ///
+    /// # Examples
+    ///
+    /// Changes are seen by the rest of the run, but not by the runs after it:
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, InputStrategy, State};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_push(state: &mut State<Vec<u64>>) {
+    ///     assert_eq!(state.input().len(), 64);
+    ///     state.input_mut().push(0);
+    ///     assert_eq!(state.input().len(), 65);
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_secs(0));
+    ///     Benchmark::with_name("push")
+    ///         .with_range(64, 64, 2)
+    ///         .with_generator(|n| (0..n).collect::<Vec<u64>>())
+    ///         .with_input_strategy(InputStrategy::Shared)
+    ///         .with_bench(pew_bench!(bm_push))
+    ///         .run_with(&config);
+    /// }
+    /// ```
+    pub fn input_mut(&mut self) -> &mut T {
+        let shared = match self.input {
+            Input::Owned(_) | Input::Fixture(_) => None,
+            Input::Shared(ref input, clone) => Some((input.clone(), clone)),
+        };
+        if let Some((input, clone)) = shared {
+            let running = !self.clock.is_paused();
+            if running {
+                self.pause();
+            }
+            let owned = clone(&input);
+            if running {
+                self.resume();
+            }
+            self.input = Input::Owned(owned);
+        }
+
+        match self.input {
+            Input::Owned(ref mut input) | Input::Fixture(ref mut input) => input,
+            Input::Shared(..) => unreachable!(),
         }
     }
 
+    pub(crate) fn with_thread(mut self, index: u64, count: u64) -> State<T> {
+        self.thread_index = index;
+        self.thread_count = count;
+        self
+    }
+
+    /// Returns the index of the thread running this state, from 0 to `thread_count() - 1`
+    ///
+    /// This is always 0 unless the benchmark runs on several threads (see
+    /// `Benchmark::with_threads`), e.g. to have one thread write while the others read.
+    pub fn thread_index(&self) -> u64 {
+        self.thread_index
+    }
+
+    /// Returns the number of threads running the bench function at the same time
+    pub fn thread_count(&self) -> u64 {
+        self.thread_count
+    }
+
+    /// Returns the number of iterations the bench function should do in this run
+    ///
+    /// Prefer `iter` where possible. Calling this tells pew that the bench function does its work
+    /// this many times, so the time of the run is divided by it.
+    pub fn iterations(&mut self) -> u64 {
+        self.uses_iterations = true;
+        self.iterations
+    }
+
+    /// Returns an iterator over the iterations the bench function should do in this run
+    ///
+    /// The work inside the loop is what gets measured. pew starts with a single iteration and
+    /// increases the count until a run takes long enough to be measured accurately, which makes it
+    /// possible to benchmark operations that only take a few nanoseconds.
+    ///
+    /// Note that the input is the same for every iteration of a run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, State};
+    /// use std::time::Duration;
+    ///
+    /// fn bm_add(state: &mut State<u64>) {
+    ///     let n = state.get_input();
+    ///     for _ in state.iter() {
+    ///         pew::do_not_optimize(n + 1);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let config = Config::new().with_min_duration(Duration::from_millis(10));
+    ///     let results = Benchmark::with_name("add")
+    ///         .with_range(1, 1, 2)
+    ///         .with_bench(pew_bench!(bm_add))
+    ///         .run_with(&config);
+    ///
+    ///     assert!(results[0].iterations > 1);
+    /// }
+    /// ```
+    pub fn iter(&mut self) -> Range<u64> {
+        0..self.iterations()
+    }
+
+    /// Runs `routine` once per iteration, on inputs produced by `setup` outside of the timed region
+    ///
+    /// The iterations of the run are split into batches (see `BatchSize`). The timer is paused
+    /// while the inputs of a batch are set up and while the outputs of the routine are dropped,
+    /// so only `routine` is measured. This pauses the timer once per batch rather than once per
+    /// iteration, which makes it a cheaper and more accurate alternative to calling `pause` and
+    /// `resume` around the setup of every iteration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{BatchSize, State};
+    ///
+    /// fn bm_sort(state: &mut State<u64>) {
+    ///     let n = state.get_input();
+    ///     state.iter_batched(
+    ///         || (0..n).rev().collect::<Vec<u64>>(),
+    ///         |mut vec| {
+    ///             vec.sort();
+    ///             vec
+    ///         },
+    ///         BatchSize::SmallInput,
+    ///     );
+    /// }
+    /// ```
+    ///
+    /// Every iteration gets an input of its own, set up in as many batches as the `BatchSize`
+    /// says:
+    ///
+    /// ```
+    /// use pew::{BatchSize, Benchmark, Config, State};
+    /// use std::cell::Cell;
+    /// use std::time::Duration;
+    ///
+    /// /// Returns the number of inputs that were set up and the number of batches they were in
+    /// fn count_batches(state: &mut State<u64>, size: BatchSize) -> (u64, u64) {
+    ///     let (inputs, batches, in_batch) = (Cell::new(0), Cell::new(0), Cell::new(false));
+    ///     let setup = || {
+    ///         inputs.set(inputs.get() + 1);
+    ///         if !in_batch.replace(true) {
+    ///             batches.set(batches.get() + 1);
+    ///         }
+    ///     };
+    ///     state.iter_batched(setup, |_| in_batch.set(false), size);
+    ///     (inputs.get(), batches.get())
+    /// }
+    ///
+    /// let sizes = [
+    ///     BatchSize::SmallInput,
+    ///     BatchSize::LargeInput,
+    ///     BatchSize::PerIteration,
+    /// ];
+    /// let config = Config::new().with_min_duration(Duration::from_millis(10));
+    /// for &size in &sizes {
+    ///     let bench = move |state: &mut State<u64>| {
+    ///         let iterations = state.iterations();
+    ///         let (inputs, batches) = count_batches(state, size);
+    ///         assert_eq!(inputs, iterations);
+    ///         match size {
+    ///             BatchSize::SmallInput => assert!(batches <= 10),
+    ///             BatchSize::LargeInput => assert!(batches <= 1000),
+    ///             BatchSize::PerIteration => assert_eq!(batches, iterations),
+    ///         }
+    ///     };
+    ///     let results = Benchmark::with_name("batches")
+    ///         .with_range(1, 1, 2)
+    ///         .with_bench(("bm_count", bench))
+    ///         .run_with(&config);
+    ///
+    ///     assert!(results[0].iterations > 10);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the state is paused.
+    pub fn iter_batched<I, O, S, R>(&mut self, mut setup: S, mut routine: R, size: BatchSize)
+    where
+        S: FnMut() -> I,
+        R: FnMut(I) -> O,
+    {
+        let iterations = self.iterations();
+        let batch_size = size.batch_size(iterations);
+
+        self.pause();
+        let mut remaining = iterations;
+        while remaining > 0 {
+            let batch = cmp::min(batch_size, remaining);
+            let inputs: Vec<I> = (0..batch).map(|_| setup()).collect();
+            let mut outputs = Vec::with_capacity(batch as usize);
+
+            self.resume();
+            for input in inputs {
+                outputs.push(routine(input));
+            }
+            self.pause();
+
+            drop(outputs);
+            remaining -= batch;
+        }
+        self.resume();
+    }
+
+    pub(crate) fn uses_iterations(&self) -> bool {
+        self.uses_iterations
+    }
+
+    /// Returns the number of times the timer was paused (and resumed) during this run
+    pub(crate) fn pauses(&self) -> u64 {
+        self.pauses
+    }
+
+    /// Sets the number of bytes processed by each iteration of this run
+    ///
+    /// The results will include the number of bytes processed per second (see
+    /// `BenchmarkResult::bytes_per_second`). This overrides the default set with
+    /// `Benchmark::with_bytes_processed`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{self, State};
+    ///
+    /// fn bm_copy(state: &mut State<Vec<u8>>) {
+    ///     let src = state.get_input();
+    ///     state.set_bytes_processed(src.len() as u64);
+    ///     for _ in state.iter() {
+    ///         pew::do_not_optimize(src.clone());
+    ///     }
+    /// }
+    /// ```
+    pub fn set_bytes_processed(&mut self, bytes: u64) {
+        self.bytes_processed = Some(bytes);
+    }
+
+    /// Sets the number of items processed by each iteration of this run
+    ///
+    /// The results will include the number of items processed per second (see
+    /// `BenchmarkResult::items_per_second`). This overrides the default set with
+    /// `Benchmark::with_items_processed`.
+    pub fn set_items_processed(&mut self, items: u64) {
+        self.items_processed = Some(items);
+    }
+
+    /// Sets the value of a user defined counter for this run
+    ///
+    /// Counters are reported alongside the time of the benchmark. By default the values of every
+    /// run are summed, but `Counter::new` takes `CounterFlags` to report e.g. the average per
+    /// iteration or the rate per second instead. Setting the same counter again in a run replaces
+    /// its value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::{Counter, CounterFlags, State};
+    ///
+    /// fn bm_sort(state: &mut State<Vec<u64>>) {
+    ///     let mut vec = state.get_input();
+    ///     let mut comparisons = 0u64;
+    ///     vec.sort_by(|a, b| {
+    ///         comparisons += 1;
+    ///         a.cmp(b)
+    ///     });
+    ///     state.counter(
+    ///         "comparisons",
+    ///         Counter::new(comparisons as f64, CounterFlags::AVG_RUNS),
+    ///     );
+    /// }
+    /// ```
+    pub fn counter<C: Into<Counter>>(&mut self, name: &str, counter: C) {
+        let counter = counter.into();
+        match self.counters.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = counter,
+            None => self.counters.push((name.to_string(), counter)),
+        }
+    }
+
+    pub(crate) fn take_counters(&mut self) -> Vec<(String, Counter)> {
+        mem::take(&mut self.counters)
+    }
+
+    pub(crate) fn bytes_processed(&self) -> Option<u64> {
+        self.bytes_processed
+    }
+
+    pub(crate) fn items_processed(&self) -> Option<u64> {
+        self.items_processed
+    }
+
     /// Pauses the benchmark timer. Useful to do any initialization work, etc.
     /// The state begins in a running (unpaused) state.
     ///
@@ -45,6 +443,7 @@ impl<T> State<T> {
     /// Panics if the state is already paused.
     pub fn pause(&mut self) {
         self.clock.pause();
+        self.pauses += 1;
     }
 
     /// Resumes the benchmark timer. Useful after any initialization work, etc.
@@ -72,14 +471,28 @@ impl<T> State<T> {
         self.clock.resume();
     }
 
-    pub fn finish(self) -> u64 {
+    pub fn finish(self) -> Elapsed {
         self.clock.stop()
     }
+
+    /// Stops the timer and returns the measured time along with the input of the run
+    pub(crate) fn finish_with_input(self) -> (Elapsed, Input<T>) {
+        (self.clock.stop(), self.input)
+    }
+
+    /// Returns the input of the run without stopping the timer, e.g. after the bench function
+    /// panicked
+    pub(crate) fn into_input(self) -> Input<T> {
+        self.input
+    }
 }
 
 impl<T: Default> State<T> {
     /// Returns the input. Either `u64` (if no generator is specified) or a user specified
-    /// `T: Clone + Default` where `T` is the return type of the last generator.
+    /// `T: Default` where `T` is the return type of the last generator.
+    ///
+    /// The input is moved out of the state, so it can only be taken once per run. If it is shared
+    /// between runs (see `InputStrategy::Shared`), a clone is returned instead.
     ///
     /// # Examples
     ///
@@ -98,10 +511,44 @@ impl<T: Default> State<T> {
     ///
     /// # Panics
     ///
-    /// Panics if the state is paused.
+    /// Panics if the state is paused, or if the input is a fixture (see `Fixture`), which every
+    /// run borrows and which could not be torn down once taken.
+    ///
+    /// ```should_panic
+    /// #[macro_use]
+    /// extern crate pew;
+    /// use pew::{Benchmark, Config, Fixture, State};
+    ///
+    /// #[derive(Default)]
+    /// struct Buffer(Vec<u8>);
+    ///
+    /// impl Fixture for Buffer {
+    ///     fn setup(size: u64) -> Self {
+    ///         Buffer(vec![0; size as usize])
+    ///     }
+    /// }
+    ///
+    /// fn bm_take(state: &mut State<Buffer>) {
+    ///     pew::do_not_optimize(state.get_input());
+    /// }
+    ///
+    /// fn main() {
+    ///     Benchmark::with_name("buffer")
+    ///         .with_range(64, 64, 2)
+    ///         .with_fixture::<Buffer>()
+    ///         .with_bench(pew_bench!(bm_take))
+    ///         .run_with(&Config::new());
+    /// }
+    /// ```
     pub fn get_input(&mut self) -> T {
         self.pause();
-        let input = mem::replace(&mut self.input, T::default());
+        let input = match self.input {
+            Input::Owned(ref mut input) => mem::take(input),
+            Input::Shared(ref input, clone) => clone(input),
+            Input::Fixture(_) => {
+                panic!("A fixture cannot be taken, use State::input or State::input_mut instead")
+            }
+        };
         self.resume();
         input
     }
@@ -0,0 +1,210 @@
+/// Summary statistics over the samples collected for a single benchmark
+///
+/// All values except `cv` are in nanoseconds. `cv` (the coefficient of variation) is the standard
+/// deviation divided by the mean, and is a unitless ratio (i.e. `0.05` means the samples deviate
+/// from the mean by 5% on average). It is useful for telling a noisy benchmark apart from a
+/// genuinely slow one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub cv: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Statistics {
+    /// Computes the statistics for a set of samples
+    ///
+    /// The standard deviation is the sample standard deviation (i.e. it uses Bessel's correction)
+    /// and percentiles are linearly interpolated between the closest ranks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pew::Statistics;
+    ///
+    /// let stats = Statistics::from_samples(&[4.0, 1.0, 3.0, 2.0, 5.0]);
+    /// assert_eq!(stats.mean, 3.0);
+    /// assert_eq!(stats.min, 1.0);
+    /// assert_eq!(stats.max, 5.0);
+    /// assert_eq!(stats.median, 3.0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `samples` is empty.
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            panic!("Cannot compute statistics without any samples");
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let stddev = if sorted.len() > 1 {
+            (sorted.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let cv = if mean > 0.0 { stddev / mean } else { 0.0 };
+
+        Statistics {
+            mean,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            median: percentile(&sorted, 0.5),
+            stddev,
+            cv,
+            p90: percentile(&sorted, 0.9),
+            p99: percentile(&sorted, 0.99),
+        }
+    }
+}
+
+/// Returns the `p`th percentile (`0 <= p <= 1`) of an already sorted, non-empty slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// The outcome of Welch's t-test for the means of two samples being equal (see `welch_t_test`)
+pub(crate) struct WelchTest {
+    /// The two-sided p-value of the test
+    pub(crate) p_value: f64,
+    /// The confidence interval of the difference of the means
+    pub(crate) interval: (f64, f64),
+}
+
+/// Runs Welch's t-test for the means of `a` and `b` being equal
+///
+/// Unlike Student's t-test, this does not assume that both samples have the same variance. The
+/// confidence interval of `mean(a) - mean(b)` has a confidence level of `1 - alpha`. Both samples
+/// must have at least 2 values.
+pub(crate) fn welch_t_test(a: &[f64], b: &[f64], alpha: f64) -> WelchTest {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let (a, b) = (Statistics::from_samples(a), Statistics::from_samples(b));
+    let (va, vb) = (a.stddev.powi(2) / na, b.stddev.powi(2) / nb);
+    let difference = a.mean - b.mean;
+    if va + vb == 0.0 {
+        return WelchTest {
+            p_value: if difference == 0.0 { 1.0 } else { 0.0 },
+            interval: (difference, difference),
+        };
+    }
+
+    let se = (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    let margin = t_critical(alpha, df) * se;
+    WelchTest {
+        p_value: t_p_value(difference / se, df),
+        interval: (difference - margin, difference + margin),
+    }
+}
+
+/// Returns the two-sided p-value of `t` in Student's t-distribution with `df` degrees of freedom
+fn t_p_value(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// Returns the `t` whose two-sided p-value is `alpha`, found by bisection
+fn t_critical(alpha: f64, df: f64) -> f64 {
+    let mut hi = 1.0;
+    while t_p_value(hi, df) > alpha {
+        hi *= 2.0;
+    }
+    let mut lo = 0.0;
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if t_p_value(mid, df) > alpha {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+/// Returns the regularized incomplete beta function `I_x(a, b)`
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only on this side of the mean of the distribution
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Evaluates the continued fraction of the incomplete beta function with Lentz's method
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-12;
+    const TINY: f64 = 1e-300;
+    let clamp = |v: f64| if v.abs() < TINY { TINY } else { v };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+/// Returns the natural logarithm of the gamma function, with the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000_000_000_190_015;
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Statistics;
+
+    #[test]
+    fn nan_samples_do_not_panic() {
+        let stats = Statistics::from_samples(&[2.0, f64::NAN, 1.0]);
+        assert_eq!(stats.min, 1.0);
+        assert!(stats.max.is_nan());
+    }
+}
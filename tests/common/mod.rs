@@ -0,0 +1,60 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Runs tests again in a child process, for the tests of what pew prints or of how it exits.
+//!
+//! The child is the test binary itself, run with an environment variable that tells it that it is
+//! the child and what to do.
+
+// Every test binary only uses some of these
+#![allow(dead_code)]
+
+use std::env;
+use std::process::{Command, Output};
+
+/// Runs the child half of a test if `var` is set (i.e. this is the child process) with its value,
+/// and the parent half otherwise, returning what the parent half returned
+pub fn run<R, C, P>(var: &str, child: C, parent: P) -> Option<R>
+where
+    C: FnOnce(String),
+    P: FnOnce() -> R,
+{
+    match env::var(var) {
+        Ok(value) => {
+            child(value);
+            None
+        }
+        Err(_) => Some(parent()),
+    }
+}
+
+/// Returns a command that runs this binary again as the child process of `run`, with `var` set
+/// to `value`
+pub fn child(var: &str, value: &str) -> Command {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command.env(var, value);
+    command
+}
+
+/// Runs only the test called `test` of this binary again as the child process of `run`, with
+/// `var` set to `value` and the other environment variables in `envs`, returning its output
+pub fn run_test(test: &str, var: &str, value: &str, envs: &[(&str, &str)]) -> Output {
+    child(var, value)
+        .args([test, "--exact", "--nocapture"])
+        .envs(envs.iter().cloned())
+        .output()
+        .expect("Failed to run the test binary")
+}
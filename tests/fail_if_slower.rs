@@ -0,0 +1,167 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks that `--fail-if-slower` fails the process once every suite has run if any benchmark
+//! regressed.
+//!
+//! This exits the process, and is configured from the command line, so this test has no harness
+//! of its own (like `libtest`). It runs itself again in a child process (with
+//! `PEW_FAIL_IF_SLOWER_CHILD` set to `run` or `run_with`) which benchmarks two suites with the
+//! flags it was given, against a baseline written by the parent.
+
+#[macro_use]
+extern crate pew;
+
+mod common;
+
+use pew::{Benchmark, Config, State};
+use std::env;
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+use std::process::Output;
+use std::time::{Duration, Instant};
+
+/// Set in the child process to the function it runs its suites with
+const VAR: &str = "PEW_FAIL_IF_SLOWER_CHILD";
+
+/// Printed by the child once every suite has run, before `pew::finish`
+const RETURNED: &str = "every suite returned";
+
+/// Takes about 50µs of CPU time
+fn bm_spin(_: &mut State<u64>) {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_micros(50) {
+        pew::do_not_optimize(());
+    }
+}
+
+fn suite(name: &str) -> Benchmark<u64> {
+    Benchmark::with_name(name)
+        .with_range(1, 1, 2)
+        .with_bench(pew_bench!(bm_spin))
+}
+
+/// Runs the suites with `run`, or with `run_with` and then `pew::finish`
+fn run_child(function: String) {
+    if function == "run" {
+        // Without `pew::finish`, which the regression gate must not depend on
+        suite("spin").run();
+        suite("spin_again").run();
+        return;
+    }
+
+    let config = Config::from_args();
+    suite("spin").run_with(&config);
+    suite("spin_again").run_with(&config);
+    eprintln!("{}", RETURNED);
+    pew::finish();
+}
+
+/// Runs the suites in a child process with `function` against a baseline whose samples are
+/// around `mean` ns, and fails if they are more than 5% slower
+fn run(test: &str, function: &str, mean: f64) -> Output {
+    let samples: Vec<f64> = [0.9, 1.0, 1.1, 1.0, 0.9, 1.1]
+        .iter()
+        .map(|s| s * mean)
+        .collect();
+    let baseline: PathBuf = env::temp_dir().join(format!("pew_fail_if_slower_{}.json", test));
+    let contents = format!(
+        r#"{{"benchmarks": {{"spin/bm_spin/1": {{"real_samples": {0:?}, "cpu_samples": {0:?}}}, "spin_again/bm_spin/1": {{"real_samples": {0:?}, "cpu_samples": {0:?}}}}}}}"#,
+        samples
+    );
+    fs::write(&baseline, contents).unwrap();
+
+    let output = common::child(VAR, function)
+        .args(["-d", "0", "--fail-if-slower", "5%", "--baseline"])
+        .arg(&baseline)
+        .output()
+        .expect("Failed to run the test binary");
+    fs::remove_file(&baseline).unwrap();
+    output
+}
+
+fn regressions_exit_with_status_1() {
+    let output = run("regressions", "run", 1_000.0);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    // Every suite ran, and is listed once
+    assert!(
+        stderr.contains("2 benchmark(s) are more than 5% slower than the baseline"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("  spin/bm_spin/1: +"), "{}", stderr);
+    assert!(stderr.contains("  spin_again/bm_spin/1: +"), "{}", stderr);
+}
+
+fn improvements_exit_with_status_0() {
+    let output = run("improvements", "run", 1_000_000.0);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(!stderr.contains("slower than the baseline"), "{}", stderr);
+}
+
+fn run_with_returns_and_finish_exits() {
+    let output = run("run_with", "run_with", 1_000.0);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    // The regressions are only reported by `pew::finish`, after `run_with` returned
+    let returned = stderr.find(RETURNED).expect(&stderr);
+    let reported = stderr
+        .find("2 benchmark(s) are more than 5%")
+        .expect(&stderr);
+    assert!(returned < reported, "{}", stderr);
+}
+
+fn baseline_is_required() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result =
+        panic::catch_unwind(|| suite("spin").run_with(&Config::new().with_fail_if_slower(5.0)));
+    panic::set_hook(hook);
+    let payload = result.expect_err("A regression gate without a baseline must panic");
+    let message = payload.downcast_ref::<&str>().unwrap();
+    assert!(message.contains("needs a baseline"), "{}", message);
+}
+
+fn reject_malformed_percentages() {
+    for percent in &["5", "abc%", "inf%"] {
+        let output = common::child(VAR, "run")
+            .args(["--baseline", "main", "--fail-if-slower", percent])
+            .output()
+            .expect("Failed to run the test binary");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // A usage error from clap rather than a panic
+        assert_eq!(output.status.code(), Some(1), "{}", stderr);
+        assert!(
+            stderr.contains(&format!("{} is not a percentage, e.g. 5%", percent)),
+            "{}",
+            stderr
+        );
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+}
+
+fn main() {
+    common::run(VAR, run_child, || {
+        regressions_exit_with_status_1();
+        improvements_exit_with_status_0();
+        run_with_returns_and_finish_exits();
+        baseline_is_required();
+        reject_malformed_percentages();
+    });
+}
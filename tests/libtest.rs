@@ -0,0 +1,157 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks that benchmarks run with `harness = false` accept the flags of libtest (as passed by
+//! `cargo bench`) and print its `test name ... bench:` lines with `--format libtest`.
+//!
+//! This test has no harness of its own, like a benchmark in `benches/`. It runs itself again in
+//! a child process (with `PEW_LIBTEST_CHILD` set) which runs the benchmarks with the flags it was
+//! given.
+
+#[macro_use]
+extern crate pew;
+extern crate regex;
+extern crate serde_json;
+
+mod common;
+
+use pew::{Benchmark, State};
+use regex::Regex;
+use serde_json::Value;
+
+/// Set in the child process
+const VAR: &str = "PEW_LIBTEST_CHILD";
+
+fn bm_sum(state: &mut State<u64>) {
+    let n = state.get_input();
+    pew::do_not_optimize((0..n).sum::<u64>());
+}
+
+fn bm_sum_bytes(state: &mut State<u64>) {
+    let n = state.get_input();
+    pew::do_not_optimize((0..n).sum::<u64>());
+    state.set_bytes_processed(n * 8);
+}
+
+fn run_child() {
+    Benchmark::with_name("sum")
+        .with_range(64, 128, 2)
+        .with_bench(pew_bench!(bm_sum))
+        .with_bench(pew_bench!(bm_sum_bytes))
+        .run();
+    pew::finish();
+}
+
+/// Runs the benchmarks in a child process with `args`, returning what they printed
+fn run(args: &[&str]) -> String {
+    let output = common::child(VAR, "1")
+        .args(args)
+        .output()
+        .expect("Failed to run the test binary");
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs the benchmarks with `--list` and `args`, returning the names that were listed
+fn list(args: &[&str]) -> Vec<String> {
+    let mut list_args = vec!["--list"];
+    list_args.extend_from_slice(args);
+    run(&list_args)
+        .lines()
+        .map(|line| line.trim_end_matches(": bench").to_string())
+        .collect()
+}
+
+fn list_every_benchmark() {
+    assert_eq!(
+        list(&[]),
+        [
+            "sum/bm_sum/64",
+            "sum/bm_sum_bytes/64",
+            "sum/bm_sum/128",
+            "sum/bm_sum_bytes/128",
+        ]
+    );
+}
+
+fn filter_by_substring() {
+    assert_eq!(
+        list(&["bm_sum_bytes"]),
+        ["sum/bm_sum_bytes/64", "sum/bm_sum_bytes/128"]
+    );
+    assert_eq!(list(&["/12"]), ["sum/bm_sum/128", "sum/bm_sum_bytes/128"]);
+    // The filter is not a regex, unlike --filter
+    assert!(list(&["sum/.*/64"]).is_empty());
+}
+
+fn filter_exact_names() {
+    assert_eq!(list(&["--exact", "sum/bm_sum/64"]), ["sum/bm_sum/64"]);
+    assert!(list(&["--exact", "bm_sum"]).is_empty());
+}
+
+fn test_runs_briefly() {
+    let output = run(&["--bench", "--test", "--format", "json"]);
+    let suite: Value = serde_json::from_str(output.trim()).unwrap();
+    let benchmarks = suite["benchmarks"].as_array().unwrap();
+    assert_eq!(benchmarks.len(), 4);
+    for benchmark in benchmarks {
+        assert_eq!(benchmark["runs"], 2, "{}", benchmark);
+        assert_eq!(benchmark["repetitions"], 1, "{}", benchmark);
+    }
+}
+
+fn libtest_lines() {
+    let output = run(&["--bench", "--test", "--format", "libtest"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 9, "{}", output);
+    assert_eq!(lines[..2], ["", "running 4 tests"]);
+    let bench = Regex::new(
+        r"^test sum/(bm_sum|bm_sum_bytes)/(64|128) +\.\.\. bench: +[0-9,]+ ns/iter \(\+/- [0-9,]+\)( = [0-9]+ MB/s)?$",
+    )
+    .unwrap();
+    for line in &lines[2..6] {
+        let captures = bench.captures(line).expect(line);
+        // Only benchmarks that set their bytes processed have a throughput
+        assert_eq!(
+            captures.get(3).is_some(),
+            &captures[1] == "bm_sum_bytes",
+            "{}",
+            line
+        );
+    }
+    assert_eq!(
+        lines[6..],
+        [
+            "",
+            "test result: ok. 0 passed; 0 failed; 0 ignored; 4 measured; 0 filtered out",
+            "",
+        ]
+    );
+}
+
+fn main() {
+    common::run(
+        VAR,
+        |_| run_child(),
+        || {
+            list_every_benchmark();
+            filter_by_substring();
+            filter_exact_names();
+            test_runs_briefly();
+            libtest_lines();
+        },
+    );
+}
@@ -0,0 +1,61 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks that `do_not_optimize` and `clobber` stop the optimizer from eliding benchmarked work.
+//!
+//! The timing tests only mean something when the tests are built with optimizations, so they are
+//! ignored in debug builds and run by `cargo test --release`. Each loop does an amount of work
+//! that takes milliseconds even on a fast machine, and would take (close to) no time at all if the
+//! optimizer removed it.
+
+extern crate pew;
+
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u64 = 100_000_000;
+
+#[test]
+#[cfg_attr(debug_assertions, ignore = "needs optimizations, run with --release")]
+fn do_not_optimize_keeps_unused_values() {
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        pew::do_not_optimize(i);
+    }
+    assert!(start.elapsed() > Duration::from_millis(5));
+}
+
+#[test]
+fn do_not_optimize_returns_its_argument() {
+    assert_eq!(pew::do_not_optimize(5 + 10), 15);
+    assert_eq!(pew::do_not_optimize(vec![1, 2, 3]), vec![1, 2, 3]);
+}
+
+// Every value stored to `slot` is overwritten before it is read, which is the point: without the
+// clobber the optimizer only has to perform the last store.
+#[allow(unused_assignments)]
+#[test]
+#[cfg_attr(debug_assertions, ignore = "needs optimizations, run with --release")]
+fn clobber_keeps_overwritten_stores() {
+    let mut slot = 0u64;
+    pew::do_not_optimize(&mut slot as *mut u64);
+
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        slot = i;
+        pew::clobber();
+    }
+    assert!(start.elapsed() > Duration::from_millis(5));
+}
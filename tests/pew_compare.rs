@@ -0,0 +1,166 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks the output of the `pew_compare` binary on every kind of file it reads.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+const CSV_HEADER: &str = "Name,Time (ns),CPU (ns),Min (ns),Max (ns),Median (ns),Std Dev (ns),\
+                          CV (%),P90 (ns),P99 (ns),Runs,Iterations,Bytes/s,Items/s\n";
+
+/// Writes `contents` to a file of its own, named after the test
+fn write(test: &str, file: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("pew_compare_{}_{}_{}", process::id(), test, file));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Compares `old` to `new`, returning the output of `pew_compare`
+fn compare(test: &str, old: &str, new: &str, flags: &[&str]) -> Output {
+    let old = write(test, "old", old);
+    let new = write(test, "new", new);
+    let output = Command::new(env!("CARGO_BIN_EXE_pew_compare"))
+        .arg(&old)
+        .arg(&new)
+        .args(flags)
+        .output()
+        .expect("Failed to run pew_compare");
+    fs::remove_file(old).unwrap();
+    fs::remove_file(new).unwrap();
+    output
+}
+
+/// Returns the cells of every row of the table printed by a successful comparison
+fn rows(output: &Output) -> Vec<Vec<String>> {
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().map(String::from).collect())
+        .collect()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn compares_csv_files() {
+    let old = format!(
+        "{}a/f/8,100,90,1,1,1,1,1,1,1,10,5,,\n\
+         a/f/8,100,110,1,1,1,1,1,1,1,10,5,,\n\
+         a/f/8_mean,100,100,,,,,,,,2,,,\n",
+        CSV_HEADER
+    );
+    let new = format!("{}a/f/8,150,100,1,1,1,1,1,1,1,10,5,,\n", CSV_HEADER);
+
+    let output = compare("csv", &old, &new, &[]);
+    assert_eq!(rows(&output), [["a/f/8", "100", "150", "+50", "1.5000"]]);
+    assert!(stderr(&output).contains("skipped 1 rows"));
+
+    let output = compare("csv_cpu", &old, &new, &["--cpu"]);
+    assert_eq!(rows(&output), [["a/f/8", "100", "100", "+0", "1.0000"]]);
+}
+
+#[test]
+fn warns_about_malformed_csv_rows() {
+    let csv = format!(
+        "{}a/f/8,100\na/f/8,100,90,1,1,1,1,1,1,1,10,5,,\n",
+        CSV_HEADER
+    );
+    let output = compare("malformed_csv", &csv, &csv, &[]);
+    assert_eq!(rows(&output), [["a/f/8", "100", "100", "+0", "1.0000"]]);
+    assert!(stderr(&output).contains("skipped 1 rows"));
+}
+
+#[test]
+fn compares_baselines() {
+    let old =
+        r#"{"benchmarks": {"a/f/8": {"real_samples": [10, 11, 9], "cpu_samples": [1, 1, 1]}}}"#;
+    let new =
+        r#"{"benchmarks": {"a/f/8": {"real_samples": [20, 21, 19], "cpu_samples": [1, 1, 1]}}}"#;
+
+    let rows = rows(&compare("baseline", old, new, &[]));
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][..5], ["a/f/8", "10", "20", "+10", "2.0000"]);
+    let p_value: f64 = rows[0][5].parse().unwrap();
+    assert!(p_value < 0.05);
+}
+
+#[test]
+fn compares_json_output() {
+    let suite = |samples: &str| {
+        format!(
+            r#"{{"suite": "a", "benchmarks": [
+                {{"name": "a/f/8", "run_type": "repetition", "real_samples": {0}}},
+                {{"name": "a/f/8", "run_type": "repetition", "real_samples": {0}}},
+                {{"name": "a/f/8_mean", "run_type": "aggregate"}}
+            ]}}
+            {{"suite": "b", "benchmarks": []}}"#,
+            samples
+        )
+    };
+
+    let rows = rows(&compare(
+        "json",
+        &suite("[10, 11, 9]"),
+        &suite("[5, 6, 4]"),
+        &[],
+    ));
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][..5], ["a/f/8", "10", "5", "-5", "0.5000"]);
+    assert!(!rows[0][5].is_empty());
+}
+
+#[test]
+fn compares_google_json_output() {
+    let document = |time: f64| {
+        format!(
+            r#"{{"context": {{}}, "benchmarks": [
+                {{"name": "a/f/8", "run_type": "iteration", "real_time": {0}, "cpu_time": {0},
+                  "time_unit": "us"}},
+                {{"name": "a/f/8_mean", "run_type": "aggregate", "real_time": {0},
+                  "cpu_time": {0}, "time_unit": "us"}}
+            ]}}"#,
+            time
+        )
+    };
+
+    let output = compare("google_json", &document(1.0), &document(2.0), &[]);
+    assert_eq!(
+        rows(&output),
+        [["a/f/8", "1000", "2000", "+1000", "2.0000"]]
+    );
+}
+
+#[test]
+fn rejects_benchmarks_without_times() {
+    let json = r#"{"suite": "a", "benchmarks": [{"name": "a/f/8", "run_type": "repetition"}]}"#;
+    let output = compare("no_times", json, json, &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("a/f/8 has no times"));
+}
+
+#[test]
+fn rejects_unknown_run_types() {
+    let json = r#"{"benchmarks": [{"name": "a/f/8", "run_type": "sample"}]}"#;
+    let output = compare("run_type", json, json, &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("unknown run_type of a/f/8"));
+}
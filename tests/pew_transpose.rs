@@ -0,0 +1,74 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks the output of the `pew_transpose` binary.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn transpose(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pew_transpose"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run pew_transpose");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn keeps_names_with_underscores() {
+    let output = transpose(
+        "Name,Time (ns)\n\
+         range_bench/bm_vector_gen/32,309\n\
+         foo/bar/32,1\n",
+    );
+    assert_eq!(output, "Size,range_bench/bm_vector_gen,foo/bar\n32,309,1\n");
+}
+
+#[test]
+fn keeps_only_the_mean_of_repetitions() {
+    let output = transpose(
+        "Name,Time (ns)\n\
+         range_bench/bm_vector_gen/32,300\n\
+         range_bench/bm_vector_gen/32,320\n\
+         range_bench/bm_vector_gen/32_mean,310\n\
+         range_bench/bm_vector_gen/32_median,310\n\
+         range_bench/bm_vector_gen/32_stddev,14\n\
+         range_bench/bm_vector_gen_BigO,9.7\n",
+    );
+    assert_eq!(output, "Size,range_bench/bm_vector_gen\n32,310\n");
+}
+
+#[test]
+fn keeps_extra_arguments_in_the_column_name() {
+    let output = transpose(
+        "Name,Time (ns)\n\
+         group/bm_table/1024/8,5\n\
+         group/bm_table/1024/8/threads:2,7\n",
+    );
+    assert_eq!(
+        output,
+        "Size,group/bm_table/8,group/bm_table/8/threads:2\n1024,5,7\n"
+    );
+}
@@ -0,0 +1,247 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks the text printed by the reporter of every `--format`.
+//!
+//! Reporters print to stdout, so every test runs itself again in a child process (with
+//! `PEW_REPORTER_FORMAT` set) which reports an empty suite followed by `PEW_REPORTER_SUITES`
+//! suites of a single, made up result.
+
+extern crate pew;
+extern crate serde_json;
+
+mod common;
+
+use pew::{
+    BenchmarkResult, Calibration, ClockSource, Context, Elapsed, Format, RunType, Statistics,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+/// Set in the child process to the format to report in
+const VAR: &str = "PEW_REPORTER_FORMAT";
+
+/// Set in the child process to the number of suites to report
+const SUITES_VAR: &str = "PEW_REPORTER_SUITES";
+
+/// Printed by the child before the reports, so that the output of the test harness is skipped
+const MARKER: &str = "--- reports ---";
+
+fn context(name: &str) -> Context {
+    Context {
+        name: name.to_string(),
+        clock: ClockSource::Monotonic,
+        calibration: Calibration {
+            source: ClockSource::Monotonic,
+            resolution: 1,
+            read: Elapsed { real: 20, cpu: 30 },
+            pause_resume: Elapsed { real: 40, cpu: 50 },
+        },
+        overhead_subtracted: false,
+    }
+}
+
+/// A result with samples of 100, 200 and 300 ns, processing 2 GB/s
+fn result(group: &str) -> BenchmarkResult {
+    let samples = vec![100.0, 200.0, 300.0];
+    BenchmarkResult {
+        group: group.to_string(),
+        function: "bm_sum".to_string(),
+        args: vec![64],
+        threads: None,
+        runs: 3,
+        iterations: 10,
+        clock: ClockSource::Monotonic,
+        real_time: Statistics::from_samples(&samples),
+        cpu_time: Statistics::from_samples(&samples),
+        real_samples: samples.clone(),
+        cpu_samples: samples,
+        run_type: RunType::Repetition(0),
+        repetitions: 1,
+        bytes_per_second: Some(2e9),
+        items_per_second: None,
+        counters: BTreeMap::new(),
+        comparison: None,
+    }
+}
+
+fn run_child(format: &str, suites: usize) {
+    let format = Format::from_name(format).unwrap();
+    println!("{}", MARKER);
+    let mut reporter = format.reporter();
+    reporter.begin_suite(&context("empty"));
+    reporter.end_suite();
+    for suite in 0..suites {
+        let group = format!("suite{}", suite);
+        reporter.begin_suite(&context(&group));
+        reporter.benchmark(&result(&group));
+        reporter.end_suite();
+    }
+    // Without `pew::finish`, which the output must not depend on
+    io::stdout().flush().unwrap();
+    // Skip the output of the test harness that follows a passing test
+    process::exit(0);
+}
+
+/// Runs `test` in a child process reporting in `format`, returning what the reporter printed
+fn run_parent(test: &str, format: &str, suites: usize) -> String {
+    let output = common::run_test(test, VAR, format, &[(SUITES_VAR, &suites.to_string())]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let start = stdout.find(MARKER).expect("The child printed no reports") + MARKER.len() + 1;
+    stdout[start..].to_string()
+}
+
+fn run(test: &str, format: &str, suites: usize) -> Option<String> {
+    common::run(
+        VAR,
+        |format| {
+            let suites = env::var(SUITES_VAR).unwrap();
+            run_child(&format, suites.parse().unwrap());
+        },
+        || run_parent(test, format, suites),
+    )
+}
+
+/// Returns the cells of a line of the console table, which are at least 2 spaces apart
+fn cells(line: &str) -> Vec<&str> {
+    line.split("  ")
+        .map(|cell| cell.trim())
+        .filter(|cell| !cell.is_empty())
+        .collect()
+}
+
+#[test]
+fn console() {
+    if let Some(output) = run("console", "console", 1) {
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 5, "{}", output);
+        assert_eq!(
+            lines[0],
+            "Clock: monotonic (1 ns resolution), overhead per run: 20 ns real / 30 ns CPU, per \
+             pause/resume: 40 ns real / 50 ns CPU"
+        );
+        assert_eq!(lines[1], "-".repeat(lines[2].len()));
+        let header = cells(lines[2]);
+        assert_eq!(header[..3], ["Benchmark", "Time", "CPU"]);
+        assert_eq!(header[header.len() - 1], "Bytes/s");
+        assert_eq!(
+            cells(lines[4]),
+            [
+                "suite0/bm_sum/64",
+                "200 ns",
+                "200 ns",
+                "100 ns",
+                "300 ns",
+                "200 ns",
+                "100 ns",
+                "50.00%",
+                "280 ns",
+                "298 ns",
+                "3",
+                "10",
+                "1.86 GiB/s",
+            ]
+        );
+    }
+}
+
+#[test]
+fn csv() {
+    if let Some(output) = run("csv", "csv", 2) {
+        assert_eq!(
+            output,
+            "Name,Time (ns),CPU (ns),Min (ns),Max (ns),Median (ns),Std Dev (ns),CV (%),P90 (ns),\
+             P99 (ns),Runs,Iterations,Bytes/s,Items/s\n\
+             suite0/bm_sum/64,200,200,100,300,200,100,50.00,280,298,3,10,2000000000,\n\
+             suite1/bm_sum/64,200,200,100,300,200,100,50.00,280,298,3,10,2000000000,\n"
+        );
+    }
+}
+
+#[test]
+fn json() {
+    if let Some(output) = run("json", "json", 2) {
+        let suites: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(suites.len(), 2, "{}", output);
+        let suite = &suites[0];
+        assert_eq!(suite["suite"], "suite0");
+        assert_eq!(suite["context"]["clock"], "monotonic");
+        assert_eq!(suite["context"]["read_overhead"]["cpu"], 30);
+        let benchmarks = suite["benchmarks"].as_array().unwrap();
+        assert_eq!(benchmarks.len(), 1);
+        let benchmark = &benchmarks[0];
+        assert_eq!(benchmark["name"], "suite0/bm_sum/64");
+        assert_eq!(benchmark["run_type"], "repetition");
+        assert_eq!(benchmark["repetition_index"], 0);
+        assert_eq!(benchmark["runs"], 3);
+        assert_eq!(
+            benchmark["real_samples"],
+            serde_json::json!([100.0, 200.0, 300.0])
+        );
+        assert_eq!(benchmark["real_time"]["mean"], 200.0);
+        assert_eq!(benchmark["cpu_time"]["stddev"], 100.0);
+        assert_eq!(benchmark["bytes_per_second"], 2e9);
+        assert_eq!(benchmark["comparison"], Value::Null);
+    }
+}
+
+#[test]
+fn google_json() {
+    if let Some(output) = run("google_json", "google_json", 2) {
+        let document: Value = serde_json::from_str(&output).unwrap();
+        assert!(document["context"]["num_cpus"].as_u64().unwrap() > 0);
+        let benchmarks = document["benchmarks"].as_array().unwrap();
+        assert_eq!(benchmarks.len(), 2, "{}", output);
+        for (index, benchmark) in benchmarks.iter().enumerate() {
+            assert_eq!(benchmark["name"], format!("suite{}/bm_sum/64", index));
+            assert_eq!(benchmark["family_index"], index);
+            assert_eq!(benchmark["per_family_instance_index"], 0);
+            assert_eq!(benchmark["run_type"], "iteration");
+            assert_eq!(benchmark["iterations"], 30);
+            assert_eq!(benchmark["real_time"], 200.0);
+            assert_eq!(benchmark["time_unit"], "ns");
+        }
+    }
+}
+
+#[test]
+fn libtest() {
+    if let Some(output) = run("libtest", "libtest", 1) {
+        assert_eq!(
+            output,
+            "\nrunning 1 test\n\
+             test suite0/bm_sum/64 ... bench:         200 ns/iter (+/- 200) = 2000 MB/s\n\
+             \n\
+             test result: ok. 0 passed; 0 failed; 0 ignored; 1 measured; 0 filtered out\n\n"
+        );
+    }
+}
+
+#[test]
+fn empty_suites() {
+    for format in &["console", "csv", "json", "google_json", "libtest"] {
+        if let Some(output) = run("empty_suites", format, 0) {
+            assert_eq!(output, "", "{} printed a suite without benchmarks", format);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+/*
+ * Copyright 2018 Akshay Nanavati <akshay.nanavati1@gmail.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks the times measured for benchmarks that run on several threads at once.
+
+#[macro_use]
+extern crate pew;
+
+use pew::{Benchmark, Config, State};
+use std::time::Duration;
+
+/// Keeps a CPU busy for a fixed amount of work, whether or not it shares it with other threads
+fn bm_busy(_: &mut State<u64>) {
+    let mut x = 0u64;
+    for i in 0..200_000 {
+        x = pew::do_not_optimize(x.wrapping_mul(31).wrapping_add(i));
+    }
+}
+
+#[test]
+fn group_real_time_is_at_least_the_cpu_time_of_a_thread() {
+    let config = Config::new()
+        .with_min_duration(Duration::from_millis(20))
+        .with_min_runs(20);
+    for &threads in &[2, 4] {
+        let results = Benchmark::with_name("busy")
+            .with_range(1, 1, 2)
+            .with_threads(threads)
+            .with_threaded_bench(pew_bench!(bm_busy))
+            .run_with(&config);
+
+        let result = &results[0];
+        assert_eq!(result.threads, Some(threads));
+        // The CPU time is summed over the threads, and no thread can use more CPU time than
+        // the wall clock time of the group it ran in
+        for (real, cpu) in result.real_samples.iter().zip(&result.cpu_samples) {
+            assert!(
+                *real >= cpu / threads as f64,
+                "{} threads: real {} ns < cpu {} ns / thread",
+                threads,
+                real,
+                cpu / threads as f64
+            );
+        }
+    }
+}